@@ -0,0 +1,123 @@
+use alloc::vec::Vec;
+use crate::{Context, CtxIdx};
+
+/// Element-wise activation functions a `Layer` applies to its neurons
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Activation {
+    /// No activation; still pushes an `identity` node so that every
+    /// activation adds exactly one node per neuron
+    Linear,
+    Tanh,
+    Softplus,
+    Swish,
+    /// `Tanh` with its derivative floored at the given value during backward
+    LeakyTanh(f64),
+}
+
+impl Activation {
+    pub fn apply(&self, ctx: &mut Context, idx: CtxIdx) -> CtxIdx {
+        match self {
+            Activation::Linear   => ctx.identity(idx),
+            Activation::Tanh     => ctx.tanh(idx),
+            Activation::Softplus => ctx.softplus(idx),
+            Activation::Swish    => ctx.swish(idx),
+            Activation::LeakyTanh(floor) => ctx.leaky_tanh(idx, *floor),
+        }
+    }
+}
+
+/// A continuous piecewise linear activation with fixed breakpoints and one
+/// learnable slope per segment, anchored at `f(0) = 0`. With breakpoints
+/// `[0.0]` this is a PReLU with learnable slopes on both sides
+#[derive(Debug)]
+pub struct PiecewiseLinear {
+    breakpoints: Vec<f64>,
+    slopes: Vec<CtxIdx>,
+}
+
+impl PiecewiseLinear {
+    /// `breakpoints` must be strictly increasing. The `breakpoints.len() + 1`
+    /// slopes start at 1, making the activation the identity
+    pub fn new(ctx: &mut Context, breakpoints: &[f64]) -> Self {
+        assert!(breakpoints.windows(2).all(|w| w[0] < w[1]),
+                "breakpoints must be strictly increasing");
+
+        let slopes = (0..=breakpoints.len())
+            .map(|_| ctx.push_param(1.0))
+            .collect();
+
+        Self { breakpoints: breakpoints.to_vec(), slopes }
+    }
+
+    pub fn breakpoints(&self) -> &[f64] {
+        &self.breakpoints
+    }
+
+    /// Slope `i` applies below breakpoint `i` (and above breakpoint `i - 1`)
+    pub fn slopes(&self) -> &[CtxIdx] {
+        &self.slopes
+    }
+
+    pub fn parameters(&self) -> impl Iterator<Item = CtxIdx> + '_ {
+        self.slopes.iter().copied()
+    }
+
+    fn segment(&self, x: f64) -> usize {
+        self.breakpoints.partition_point(|&b| b <= x)
+    }
+
+    /// Integrates the slopes from 0 to `x`: every segment crossed on the way
+    /// adds a constant span, and the one holding `x` adds `slope * (x - pos)`,
+    /// so `x` itself gets the gradient of its own segment's slope
+    pub fn forward(&self, ctx: &mut Context, x: CtxIdx) -> CtxIdx {
+        let target = self.segment(ctx.value(x));
+        let mut segment = self.segment(0.0);
+        let mut pos = 0.0;
+
+        let mut terms = Vec::new();
+        while segment != target {
+            let (end, next) = if segment < target {
+                (self.breakpoints[segment], segment + 1)
+            } else {
+                (self.breakpoints[segment - 1], segment - 1)
+            };
+
+            if end != pos {
+                let span = ctx.push(end - pos);
+                terms.push(ctx.mul(self.slopes[segment], span));
+            }
+            pos = end;
+            segment = next;
+        }
+
+        let start = ctx.push(pos);
+        let offset = ctx.sub(x, start);
+        terms.push(ctx.mul(self.slopes[target], offset));
+        ctx.sum(&terms)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn piecewise_linear_starts_as_identity() {
+        let mut ctx = Context::new();
+        let pwl = PiecewiseLinear::new(&mut ctx, &[-1.0, 1.0]);
+
+        let xs = ctx.push_many(&[2.5, -0.5, -3.0]);
+        let ys: Vec<_> = xs.iter().map(|&x| pwl.forward(&mut ctx, x)).collect();
+        for (&x, &y) in xs.iter().zip(&ys) {
+            assert_eq!(ctx.value(y), ctx.value(x));
+        }
+
+        // Each slope's gradient is the length of its segment that the inputs
+        // cover on their way from 0, summed over the inputs
+        let total = ctx.sum(&ys);
+        ctx.backward(total);
+        let grads: Vec<_> = pwl.parameters().map(|s| ctx.grad(s)).collect();
+        assert_eq!(grads, [-2.0, -0.5, 1.5]);
+        assert!(xs.iter().all(|&x| ctx.grad(x) == 1.0));
+    }
+}
@@ -0,0 +1,59 @@
+use alloc::vec::Vec;
+use crate::Rng;
+
+/// Mixup augmentation: every sample is interpolated with a randomly chosen
+/// other sample, `x = lambda * x_i + (1 - lambda) * x_j`, and so are the
+/// targets. `lambda ~ Beta(alpha, alpha)` is drawn per sample; `alpha <= 0`
+/// disables mixing (`lambda = 1`), as in the reference implementation
+pub fn mixup(inputs: &[Vec<f64>], targets: &[f64], rng: &mut Rng,
+             alpha: f64) -> (Vec<Vec<f64>>, Vec<f64>) {
+    assert_eq!(inputs.len(), targets.len(),
+               "inputs and targets differ in length");
+
+    let n = inputs.len();
+    let mut mixed_inputs  = Vec::with_capacity(n);
+    let mut mixed_targets = Vec::with_capacity(n);
+
+    for i in 0..n {
+        let lambda = if alpha > 0.0 { rng.beta(alpha, alpha) } else { 1.0 };
+        let j = (rng.rand() * n as f64) as usize;
+
+        mixed_inputs.push(inputs[i].iter().zip(inputs[j].iter())
+            .map(|(&a, &b)| lambda * a + (1.0 - lambda) * b)
+            .collect());
+        mixed_targets.push(lambda * targets[i] + (1.0 - lambda) * targets[j]);
+    }
+
+    (mixed_inputs, mixed_targets)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn samples() -> (Vec<Vec<f64>>, Vec<f64>) {
+        let inputs = vec![vec![1.0, 2.0], vec![-3.0, 0.5], vec![4.0, -1.0]];
+        let targets = inputs.iter().map(|x| x[0]).collect();
+        (inputs, targets)
+    }
+
+    #[test]
+    fn mixup_without_mixing_is_unchanged() {
+        let (inputs, targets) = samples();
+        let mixed = mixup(&inputs, &targets, &mut Rng::new(1), 0.0);
+        assert_eq!(mixed, (inputs, targets));
+    }
+
+    #[test]
+    fn mixup_mixes_inputs_and_targets_alike() {
+        // With every target equal to its sample's first input, a shared
+        // lambda keeps them equal after mixing
+        let (inputs, targets) = samples();
+        let (mixed_inputs, mixed_targets) = mixup(&inputs, &targets,
+                                                  &mut Rng::new(1), 0.4);
+        for (x, t) in mixed_inputs.iter().zip(mixed_targets) {
+            assert!((x[0] - t).abs() < 1e-12);
+        }
+        assert_ne!(mixed_inputs, inputs);
+    }
+}
@@ -0,0 +1,59 @@
+use crate::{Rng, math};
+
+/// Parameter initialization schemes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Init {
+    /// Uniform in `[-1, 1]`
+    Uniform,
+
+    /// Uniform in `[-bound, bound]` with `bound = sqrt(6 / fan_in)`
+    KaimingUniform,
+
+    /// Normal with mean 0 and `std = sqrt(2 / (fan_in + fan_out))`
+    XavierNormal,
+}
+
+impl Init {
+    /// The symmetric range uniform samples are drawn from, or the standard
+    /// deviation of normal ones
+    pub fn bound(&self, fan_in: usize, fan_out: usize) -> f64 {
+        match self {
+            Init::Uniform        => 1.0,
+            Init::KaimingUniform => math::sqrt(6.0 / fan_in as f64),
+            Init::XavierNormal   => {
+                math::sqrt(2.0 / (fan_in + fan_out) as f64)
+            },
+        }
+    }
+
+    /// Draws a single parameter value for a neuron with `fan_in` inputs in a
+    /// layer of `fan_out` neurons
+    pub fn sample(&self, rng: &mut Rng, fan_in: usize, fan_out: usize) -> f64 {
+        let bound = self.bound(fan_in, fan_out);
+        match self {
+            Init::XavierNormal => rng.normal(0.0, bound),
+            _                  => rng.range(-bound, bound),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kaiming_uniform_stays_within_bound() {
+        let mut rng = Rng::new(9);
+        let bound = Init::KaimingUniform.bound(24, 8);
+        assert_eq!(bound, 0.5);
+
+        let samples: Vec<f64> = (0..10_000)
+            .map(|_| Init::KaimingUniform.sample(&mut rng, 24, 8))
+            .collect();
+        assert!(samples.iter().all(|s| (-bound..=bound).contains(s)));
+
+        // The whole range is used, not just a narrow part of it
+        let max = samples.iter().fold(0.0f64, |m, s| m.max(s.abs()));
+        assert!(max > 0.99 * bound);
+    }
+}
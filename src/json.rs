@@ -0,0 +1,441 @@
+use alloc::{string::String, vec::Vec};
+use core::fmt::Write;
+use crate::{Context, Activation, MultiLayerPerceptron};
+use crate::net::{Neuron, Layer};
+
+/// Errors produced by `MultiLayerPerceptron::from_json`
+#[derive(Debug, Clone, PartialEq)]
+pub enum JsonError {
+    /// The input isn't valid JSON; `pos` is the byte offset of the problem
+    Syntax { pos: usize },
+
+    /// The JSON is valid but doesn't describe a network. The message names
+    /// what was missing or malformed
+    Schema(&'static str),
+}
+
+/// A parsed JSON value
+#[derive(Debug)]
+enum Json {
+    Null,
+    /// `true` or `false`; nothing in the schema reads booleans
+    Bool,
+    Num(f64),
+    Str(String),
+    Arr(Vec<Json>),
+    Obj(Vec<(String, Json)>),
+}
+
+impl Json {
+    fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Obj(fields) => fields.iter()
+                .find(|(k, _)| k == key)
+                .map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    fn array(&self, what: &'static str) -> Result<&[Json], JsonError> {
+        match self {
+            Json::Arr(items) => Ok(items),
+            _ => Err(JsonError::Schema(what)),
+        }
+    }
+
+    /// Numbers, with `null` standing in for non-finite ones
+    fn num(&self, what: &'static str) -> Result<f64, JsonError> {
+        match self {
+            Json::Num(n) => Ok(*n),
+            Json::Null   => Ok(f64::NAN),
+            _            => Err(JsonError::Schema(what)),
+        }
+    }
+}
+
+/// How deeply values may nest. A network needs 7 levels; the limit only
+/// stops hostile input from overflowing the stack
+const MAX_DEPTH: usize = 128;
+
+/// Recursive descent parser over the raw bytes
+struct Parser<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    /// Values currently being parsed, i.e. the nesting depth
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn error<T>(&self) -> Result<T, JsonError> {
+        Err(JsonError::Syntax { pos: self.pos })
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.bytes.get(self.pos).is_some_and(u8::is_ascii_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_whitespace();
+        self.bytes.get(self.pos).copied()
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), JsonError> {
+        if self.peek() != Some(byte) { return self.error(); }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn literal(&mut self, word: &str, val: Json) -> Result<Json, JsonError> {
+        if !self.bytes[self.pos..].starts_with(word.as_bytes()) {
+            return self.error();
+        }
+        self.pos += word.len();
+        Ok(val)
+    }
+
+    fn value(&mut self) -> Result<Json, JsonError> {
+        if self.depth == MAX_DEPTH { return self.error(); }
+        self.depth += 1;
+        let val = match self.peek() {
+            Some(b'n') => self.literal("null", Json::Null),
+            Some(b't') => self.literal("true", Json::Bool),
+            Some(b'f') => self.literal("false", Json::Bool),
+            Some(b'"') => self.string().map(Json::Str),
+            Some(b'[') => self.array(),
+            Some(b'{') => self.object(),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            _ => self.error(),
+        };
+        self.depth -= 1;
+        val
+    }
+
+    fn number(&mut self) -> Result<Json, JsonError> {
+        let start = self.pos;
+        let numeric = |b: &u8| matches!(b, b'0'..=b'9' | b'-' | b'+' | b'.'
+                                           | b'e' | b'E');
+        while self.bytes.get(self.pos).is_some_and(numeric) {
+            self.pos += 1;
+        }
+
+        // Only ASCII was consumed, so the slice is valid UTF-8
+        let text = core::str::from_utf8(&self.bytes[start..self.pos])
+            .unwrap_or_default();
+        match text.parse() {
+            Ok(n) => Ok(Json::Num(n)),
+            Err(_) => Err(JsonError::Syntax { pos: start }),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, JsonError> {
+        self.expect(b'"')?;
+        let mut out = Vec::new();
+        loop {
+            let Some(&byte) = self.bytes.get(self.pos) else {
+                return self.error();
+            };
+            self.pos += 1;
+
+            match byte {
+                b'"' => break,
+                b'\\' => {
+                    let Some(&escape) = self.bytes.get(self.pos) else {
+                        return self.error();
+                    };
+                    self.pos += 1;
+
+                    let c = match escape {
+                        b'"'  => '"',
+                        b'\\' => '\\',
+                        b'/'  => '/',
+                        b'b'  => '\u{8}',
+                        b'f'  => '\u{c}',
+                        b'n'  => '\n',
+                        b'r'  => '\r',
+                        b't'  => '\t',
+                        b'u'  => self.unicode_escape()?,
+                        _     => return self.error(),
+                    };
+                    let mut buf = [0; 4];
+                    out.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                },
+                _ => out.push(byte),
+            }
+        }
+
+        String::from_utf8(out).or_else(|_| self.error())
+    }
+
+    /// The 4 hex digits of a `\u` escape; surrogate pairs aren't supported
+    fn unicode_escape(&mut self) -> Result<char, JsonError> {
+        let code = self.bytes.get(self.pos..self.pos + 4)
+            .and_then(|hex| core::str::from_utf8(hex).ok())
+            .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+            .and_then(char::from_u32);
+
+        match code {
+            Some(c) => {
+                self.pos += 4;
+                Ok(c)
+            },
+            None => self.error(),
+        }
+    }
+
+    fn array(&mut self) -> Result<Json, JsonError> {
+        self.expect(b'[')?;
+        let mut items = Vec::new();
+        if self.peek() == Some(b']') {
+            self.pos += 1;
+            return Ok(Json::Arr(items));
+        }
+
+        loop {
+            items.push(self.value()?);
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b']') => break,
+                _ => return self.error(),
+            }
+        }
+        self.pos += 1;
+        Ok(Json::Arr(items))
+    }
+
+    fn object(&mut self) -> Result<Json, JsonError> {
+        self.expect(b'{')?;
+        let mut fields = Vec::new();
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+            return Ok(Json::Obj(fields));
+        }
+
+        loop {
+            let key = self.string()?;
+            self.expect(b':')?;
+            fields.push((key, self.value()?));
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b'}') => break,
+                _ => return self.error(),
+            }
+        }
+        self.pos += 1;
+        Ok(Json::Obj(fields))
+    }
+}
+
+/// Writes `val` so that it parses back to the same bits, or `null` if it
+/// isn't representable in JSON
+fn write_num(out: &mut String, val: f64) {
+    if val.is_finite() {
+        let _ = write!(out, "{val:?}");
+    } else {
+        out.push_str("null");
+    }
+}
+
+fn write_activation(out: &mut String, activation: Activation) {
+    match activation {
+        Activation::Linear   => out.push_str("\"linear\""),
+        Activation::Tanh     => out.push_str("\"tanh\""),
+        Activation::Softplus => out.push_str("\"softplus\""),
+        Activation::Swish    => out.push_str("\"swish\""),
+        Activation::LeakyTanh(floor) => {
+            out.push_str("{\"leaky_tanh\":");
+            write_num(out, floor);
+            out.push('}');
+        },
+    }
+}
+
+fn read_activation(json: &Json) -> Result<Activation, JsonError> {
+    let invalid = JsonError::Schema("unknown activation");
+    match json {
+        Json::Str(name) => match name.as_str() {
+            "linear"   => Ok(Activation::Linear),
+            "tanh"     => Ok(Activation::Tanh),
+            "softplus" => Ok(Activation::Softplus),
+            "swish"    => Ok(Activation::Swish),
+            _          => Err(invalid),
+        },
+        _ => {
+            let floor = json.get("leaky_tanh").ok_or(invalid)?;
+            Ok(Activation::LeakyTanh(floor.num("leaky_tanh floor")?))
+        },
+    }
+}
+
+impl MultiLayerPerceptron {
+    /// Describes the network as JSON:
+    /// `{"layers": [{"activation": .., "neurons": [{"weights": [..],
+    /// "bias": ..}]}]}`. Activations are `"linear"`, `"tanh"`, `"softplus"`,
+    /// `"swish"` or `{"leaky_tanh": floor}`. Bias-less neurons have no
+    /// `"bias"` key, and non-finite parameters are written as `null` (read
+    /// back as NaN)
+    pub fn to_json(&self, ctx: &Context) -> String {
+        let mut out = String::from("{\"layers\":[");
+        for (layer_idx, layer) in self.layers().iter().enumerate() {
+            if layer_idx > 0 { out.push(','); }
+            out.push_str("{\"activation\":");
+            write_activation(&mut out, layer.activation());
+
+            out.push_str(",\"neurons\":[");
+            for (neuron_idx, neuron) in layer.neurons().iter().enumerate() {
+                if neuron_idx > 0 { out.push(','); }
+                out.push_str("{\"weights\":[");
+                for (i, &weight) in neuron.weight_indices().iter().enumerate() {
+                    if i > 0 { out.push(','); }
+                    write_num(&mut out, ctx.value(weight));
+                }
+
+                out.push(']');
+                if let Some(bias) = neuron.bias_index() {
+                    out.push_str(",\"bias\":");
+                    write_num(&mut out, ctx.value(bias));
+                }
+                out.push('}');
+            }
+            out.push_str("]}");
+        }
+        out.push_str("]}");
+        out
+    }
+
+    /// Rebuilds a network described by `to_json`, pushing its parameters
+    /// into `ctx`. Every neuron's weight count must match the previous
+    /// layer's size (and be the same across the first layer). The whole
+    /// description is validated first, so nothing is pushed on error
+    pub fn from_json(ctx: &mut Context,
+                     json: &str) -> Result<Self, JsonError> {
+        let mut parser = Parser { bytes: json.as_bytes(), pos: 0, depth: 0 };
+        let root = parser.value()?;
+        if parser.peek().is_some() { return parser.error(); }
+
+        let layers_json = root.get("layers")
+            .ok_or(JsonError::Schema("missing layers"))?
+            .array("layers must be an array")?;
+
+        // `(activation, [(bias, weights)])` per layer
+        let mut fan_in = None;
+        let mut parsed = Vec::with_capacity(layers_json.len());
+        for layer in layers_json {
+            let activation = layer.get("activation")
+                .ok_or(JsonError::Schema("missing activation"))
+                .and_then(read_activation)?;
+            let neurons_json = layer.get("neurons")
+                .ok_or(JsonError::Schema("missing neurons"))?
+                .array("neurons must be an array")?;
+            if neurons_json.is_empty() {
+                return Err(JsonError::Schema("layer has no neurons"));
+            }
+
+            let mut neurons = Vec::with_capacity(neurons_json.len());
+            for neuron in neurons_json {
+                let weights = neuron.get("weights")
+                    .ok_or(JsonError::Schema("missing weights"))?
+                    .array("weights must be an array")?;
+                if weights.is_empty() {
+                    return Err(JsonError::Schema("neuron has no weights"));
+                }
+                if *fan_in.get_or_insert(weights.len()) != weights.len() {
+                    return Err(JsonError::Schema("mismatched layer sizes"));
+                }
+
+                let bias = neuron.get("bias")
+                    .map(|bias| bias.num("bias"))
+                    .transpose()?;
+                let weights = weights.iter()
+                    .map(|w| w.num("weight"))
+                    .collect::<Result<Vec<_>, JsonError>>()?;
+                neurons.push((bias, weights));
+            }
+
+            fan_in = Some(neurons.len());
+            parsed.push((activation, neurons));
+        }
+
+        let layers = parsed.into_iter().map(|(activation, neurons)| {
+            let neurons = neurons.into_iter().map(|(bias, weights)| {
+                // Pushed in the same order as a freshly built neuron
+                let bias = bias.map(|b| ctx.push_param(b));
+                let weights = weights.into_iter()
+                    .map(|w| ctx.push_param(w))
+                    .collect();
+                Neuron::from_parts(weights, bias)
+            }).collect();
+            Layer::from_parts(neurons, activation)
+        }).collect();
+
+        Ok(Self::from_layers(layers))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::num::NonZero;
+    use crate::{MlpBuilder, Rng};
+
+    fn size(n: usize) -> NonZero<usize> {
+        NonZero::new(n).unwrap()
+    }
+
+    #[test]
+    fn round_trip_rebuilds_an_equivalent_model() {
+        let mut ctx = Context::new();
+        let mut rng = Rng::new(3);
+        let models = [
+            MlpBuilder::new(size(3))
+                .layer(size(4), Activation::LeakyTanh(0.05))
+                .layer(size(2), Activation::Swish)
+                .layer(size(1), Activation::Linear)
+                .build(&mut ctx, &mut rng),
+            MlpBuilder::new(size(2))
+                .layer(size(2), Activation::Softplus)
+                .bias(false)
+                .build(&mut ctx, &mut rng),
+        ];
+
+        for model in models {
+            let json = model.to_json(&ctx);
+            let copy = MultiLayerPerceptron::from_json(&mut ctx, &json)
+                .unwrap();
+            assert_eq!(copy.to_json(&ctx), json);
+
+            let n_inputs = model.layer(0).unwrap().neurons()[0].fan_in();
+            let x = ctx.push_iter((0..n_inputs).map(|i| i as f64 - 0.5));
+            let a = model.forward(&mut ctx, &x);
+            let b = copy.forward(&mut ctx, &x);
+            assert_eq!(ctx.values(&a).collect::<Vec<_>>(),
+                       ctx.values(&b).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn invalid_json_pushes_nothing() {
+        let mut ctx = Context::new();
+        let json = r#"{"layers": [
+            {"activation": "tanh", "neurons": [{"weights": [1, 2], "bias": 0}]},
+            {"activation": "tanh", "neurons": [{"weights": [1, 2]}]}]}"#;
+        assert_eq!(MultiLayerPerceptron::from_json(&mut ctx, json).err(),
+                   Some(JsonError::Schema("mismatched layer sizes")));
+        assert_eq!(ctx.len(), 0);
+
+        let json = r#"{"layers": [{"activation": "tanh",
+            "neurons": [{"weights": [1, 2]}, {"weights": [1, "2"]}]}]}"#;
+        assert_eq!(MultiLayerPerceptron::from_json(&mut ctx, json).err(),
+                   Some(JsonError::Schema("weight")));
+        assert_eq!(ctx.len(), 0);
+    }
+
+    #[test]
+    fn deep_nesting_is_a_syntax_error() {
+        let mut ctx = Context::new();
+        let json = "[".repeat(100_000);
+        assert_eq!(MultiLayerPerceptron::from_json(&mut ctx, &json).err(),
+                   Some(JsonError::Syntax { pos: MAX_DEPTH }));
+    }
+}
@@ -1,9 +1,30 @@
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
 extern crate alloc;
 
 pub mod value;
 pub mod net;
 pub mod rng;
+pub mod optim;
+pub mod metrics;
+pub mod train;
+pub mod init;
+pub mod data;
+pub mod serialize;
+pub mod loss;
+pub mod activation;
+mod math;
+#[cfg(feature = "json")]
+pub mod json;
 
-pub use value::{Value, Context, CtxIdx};
+pub use value::{Value, Context, CtxIdx, OpType, BackwardError,
+                ValidationError};
+pub use serialize::DeserializeError;
+#[cfg(feature = "json")]
+pub use json::JsonError;
 pub use rng::Rng;
-pub use net::{Neuron, Layer, MultiLayerPerceptron};
+pub use net::{Neuron, Layer, MultiLayerPerceptron, Ensemble, MlpBuilder};
+pub use activation::{Activation, PiecewiseLinear};
+pub use optim::{EmaShadow, Optimizer, Sgd, Lookahead, TrustRatio};
+pub use init::Init;
+pub use train::{Trainer, History, Samples};
@@ -0,0 +1,221 @@
+use alloc::{vec, vec::Vec};
+use crate::{Context, CtxIdx};
+
+/// How per-element losses are combined
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Reduction {
+    Sum,
+    Mean,
+
+    /// Keep the per-element losses
+    None,
+}
+
+/// `ln(x + eps)` with the context's `eps`, so that `x == 0` stays finite
+fn ln_eps(ctx: &mut Context, x: CtxIdx) -> CtxIdx {
+    let eps = ctx.push(ctx.eps());
+    let x = ctx.add(x, eps);
+    ctx.ln(x)
+}
+
+/// `KL(p || q) = sum(p_i * (ln(p_i) - ln(q_i)))` over two probability
+/// distributions
+pub fn kl_divergence(ctx: &mut Context, p: &[CtxIdx],
+                     q: &[CtxIdx]) -> CtxIdx {
+    assert_eq!(p.len(), q.len(), "distributions differ in length");
+
+    let terms: Vec<CtxIdx> = p.iter().zip(q.iter())
+        .map(|(&pi, &qi)| {
+            let ln_p = ln_eps(ctx, pi);
+            let ln_q = ln_eps(ctx, qi);
+            let diff = ctx.sub(ln_p, ln_q);
+            ctx.mul(pi, diff)
+        })
+        .collect();
+    ctx.sum(&terms)
+}
+
+/// Squared errors `(pred_i - target_i)^2`, reduced according to `reduction`.
+/// `Sum` and `Mean` return a single index, `None` one index per element
+pub fn mse_reduce(ctx: &mut Context, preds: &[CtxIdx], targets: &[f64],
+                  reduction: Reduction) -> Vec<CtxIdx> {
+    assert_eq!(preds.len(), targets.len(),
+               "predictions and targets differ in length");
+
+    let errors: Vec<CtxIdx> = preds.iter().zip(targets.iter())
+        .map(|(&pred, &target)| {
+            let target = ctx.push(target);
+            let diff   = ctx.sub(pred, target);
+            ctx.square(diff)
+        })
+        .collect();
+
+    match reduction {
+        Reduction::Sum  => vec![ctx.sum(&errors)],
+        Reduction::Mean => vec![ctx.mean(&errors)],
+        Reduction::None => errors,
+    }
+}
+
+/// Mean squared error
+pub fn mse(ctx: &mut Context, preds: &[CtxIdx], targets: &[f64]) -> CtxIdx {
+    mse_reduce(ctx, preds, targets, Reduction::Mean)[0]
+}
+
+/// Focal loss `sum(-t_i * (1 - p_i)^gamma * ln(p_i))` over predicted
+/// probabilities, down-weighting confidently correct predictions. `gamma = 0`
+/// is plain cross-entropy. Both `1 - p_i` and `p_i` are offset by the
+/// context's `eps` to keep the `pow` and `ln` gradients finite
+pub fn focal(ctx: &mut Context, preds: &[CtxIdx], targets: &[f64],
+             gamma: f64) -> CtxIdx {
+    assert_eq!(preds.len(), targets.len(),
+               "predictions and targets differ in length");
+
+    let mut terms = Vec::new();
+    for (&p, &t) in preds.iter().zip(targets.iter()) {
+        // Classes with a zero target don't contribute
+        if t == 0.0 { continue; }
+
+        let one   = ctx.push(1.0);
+        let eps   = ctx.push(ctx.eps());
+        let q     = ctx.sub(one, p);
+        let q     = ctx.add(q, eps);
+        let gamma = ctx.push(gamma);
+        let scale = ctx.pow(q, gamma);
+
+        let ln_p  = ln_eps(ctx, p);
+        let term  = ctx.mul(scale, ln_p);
+        let coeff = ctx.push(-t);
+        terms.push(ctx.mul(coeff, term));
+    }
+    ctx.sum(&terms)
+}
+
+/// Mean squared error over a batch of multi-output predictions, averaged
+/// over both the batch and the output dimension
+pub fn mse_matrix(ctx: &mut Context, preds: &[Vec<CtxIdx>],
+                  targets: &[Vec<f64>]) -> CtxIdx {
+    assert_eq!(preds.len(), targets.len(),
+               "prediction and target batches differ in length");
+
+    let errors: Vec<CtxIdx> = preds.iter().zip(targets.iter())
+        .flat_map(|(p, t)| mse_reduce(ctx, p, t, Reduction::None))
+        .collect();
+    ctx.mean(&errors)
+}
+
+/// Cross-entropy `-ln(softmax(logits)[label])` for an integer class label,
+/// computed stably as `logsumexp(logits) - logits[label]`. Its gradient
+/// w.r.t. the logits is `softmax(logits) - onehot(label)`
+pub fn sparse_cross_entropy(ctx: &mut Context, logits: &[CtxIdx],
+                            label: usize) -> CtxIdx {
+    assert!(label < logits.len(), "label {label} out of range");
+
+    // The maximum is shifted out as a constant, exactly as in `softmax`
+    let max = ctx.values(logits).fold(f64::NEG_INFINITY, f64::max);
+    let max = ctx.push(max);
+
+    let exps: Vec<CtxIdx> = logits.iter()
+        .map(|&l| {
+            let shifted = ctx.sub(l, max);
+            ctx.exp(shifted)
+        })
+        .collect();
+    let total = ctx.sum(&exps);
+    let lse   = ctx.ln(total);
+    let lse   = ctx.add(lse, max);
+
+    ctx.sub(lse, logits[label])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kl_divergence_is_zero_on_itself_and_positive_otherwise() {
+        let mut ctx = Context::new();
+        let p = ctx.push_many(&[0.2, 0.5, 0.3]);
+        let q = ctx.push_many(&[0.4, 0.4, 0.2]);
+        let zero = ctx.push_many(&[1.0, 0.0, 0.0]);
+
+        let same = kl_divergence(&mut ctx, &p, &p);
+        assert!(ctx.value(same).abs() < 1e-9);
+
+        let forward  = kl_divergence(&mut ctx, &p, &q);
+        let backward = kl_divergence(&mut ctx, &q, &p);
+        assert!(ctx.value(forward) > 0.0 && ctx.value(backward) > 0.0);
+
+        // Zero probabilities on either side stay finite
+        let guarded = kl_divergence(&mut ctx, &zero, &q);
+        ctx.backward(guarded);
+        assert!(ctx.value(guarded).is_finite());
+        ctx.assert_finite();
+    }
+
+    #[test]
+    fn mse_reductions_agree() {
+        let mut ctx = Context::new();
+        let preds = ctx.push_many(&[1.0, -2.0, 0.5, 3.0]);
+        let targets = [0.0, -1.0, 2.5, 3.0];
+
+        let sum  = mse_reduce(&mut ctx, &preds, &targets, Reduction::Sum)[0];
+        let mean = mse_reduce(&mut ctx, &preds, &targets, Reduction::Mean)[0];
+        let each = mse_reduce(&mut ctx, &preds, &targets, Reduction::None);
+
+        assert_eq!(ctx.value(sum), 6.0);
+        assert_eq!(ctx.value(sum), 4.0 * ctx.value(mean));
+        assert_eq!(ctx.values(&each).collect::<Vec<_>>(), [1.0, 1.0, 4.0, 0.0]);
+        let default = mse(&mut ctx, &preds, &targets);
+        assert_eq!(ctx.value(default), ctx.value(mean));
+    }
+
+    #[test]
+    fn focal_reduces_to_cross_entropy() {
+        let mut ctx = Context::new();
+        let preds = ctx.push_many(&[0.7, 0.2, 0.1]);
+        let targets = [1.0, 0.0, 0.0];
+
+        let loss = focal(&mut ctx, &preds, &targets, 0.0);
+        assert!((ctx.value(loss) + 0.7f64.ln()).abs() < 1e-9);
+
+        // A confident correct prediction barely contributes with gamma > 0
+        let confident = ctx.push_many(&[0.999, 0.001]);
+        let easy = focal(&mut ctx, &confident, &[1.0, 0.0], 2.0);
+        assert!(ctx.value(easy) < 1e-8);
+        let plain = focal(&mut ctx, &confident, &[1.0, 0.0], 0.0);
+        assert!(ctx.value(easy) < 1e-5 * ctx.value(plain));
+    }
+
+    #[test]
+    fn mse_matrix_averages_over_batch_and_outputs() {
+        let mut ctx = Context::new();
+        let preds = vec![ctx.push_many(&[1.0, 2.0, 3.0]),
+                         ctx.push_many(&[0.0, -1.0, 2.0])];
+        let targets = vec![vec![1.0, 0.0, 3.0], vec![1.0, 1.0, 0.0]];
+
+        // Squared errors 0, 4, 0 and 1, 4, 4
+        let loss = mse_matrix(&mut ctx, &preds, &targets);
+        assert!((ctx.value(loss) - 13.0 / 6.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn sparse_cross_entropy_gradient_is_softmax_minus_onehot() {
+        let mut ctx = Context::new();
+        let logits = ctx.push_many(&[1.0, 3.0, -0.5, 200.0]);
+        let loss = sparse_cross_entropy(&mut ctx, &logits, 1);
+        assert!(ctx.value(loss).is_finite() && ctx.value(loss) > 0.0);
+        ctx.backward(loss);
+
+        let probs = ctx.softmax(&logits);
+        let grads: Vec<f64> = logits.iter().map(|&l| ctx.grad(l)).collect();
+        assert!(grads[1] < 0.0);
+        assert!(grads.iter().enumerate().all(|(i, &g)| i == 1 || g > 0.0));
+        assert!(grads.iter().sum::<f64>().abs() < 1e-12);
+
+        for (i, (&g, p)) in grads.iter().zip(ctx.values(&probs)).enumerate() {
+            let onehot = if i == 1 { 1.0 } else { 0.0 };
+            assert!((g - (p - onehot)).abs() < 1e-12);
+        }
+    }
+}
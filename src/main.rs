@@ -1,32 +1,23 @@
-use core::num::NonZero;
-use neurnet::{Rng, Context, CtxIdx, MultiLayerPerceptron as MLP};
+use neurnet::MultiLayerPerceptron as MLP;
+use neurnet::loss;
 
 fn rdtsc() -> usize {
     unsafe { core::arch::x86_64::_rdtsc() as usize }
 }
 
 fn main() {
-    let inputs = vec![
+    let inputs = [
         vec![2.0,  3.0, -1.0],
         vec![3.0, -1.0,  0.5],
         vec![0.5,  1.0,  1.0],
         vec![1.0,  1.0, -1.0],
     ];
 
-    let targets = vec![1.0, -1.0, -1.0, 1.0];
+    let targets = [1.0, -1.0, -1.0, 1.0];
     let learning_rate = 0.1;
     let epochs = 20;
 
-    let mut ctx = Context::new();
-    let mut rng = Rng::new(rdtsc());
-
-    let mlp = MLP::new(
-        &mut ctx,
-        &mut rng,
-        &[NonZero::new(3).unwrap(),
-          NonZero::new(4).unwrap(),
-          NonZero::new(4).unwrap(),
-          NonZero::new(1).unwrap()]);
+    let (mut ctx, _rng, mlp) = MLP::quick(&[3, 4, 4, 1], rdtsc()).unwrap();
 
     for epoch in 0..epochs {
         // Store loss terms for each sample
@@ -34,20 +25,12 @@ fn main() {
 
         // Forward pass: calculate predictions and loss for each input-target pair
         for (input, &target) in inputs.iter().zip(targets.iter()) {
-            // Push each input component to the Context
-            let indices: Vec<CtxIdx> =
-                input.iter().map(|&i| ctx.push(i)).collect();
-
-            // Forward pass through the MLP
-            let pred_idx = mlp.forward(&mut ctx, &indices)[0];
-
-            // Compute squared error (y_pred - y_target)^2
-            let target_idx       = ctx.push(target);
-            let diff_idx         = ctx.sub(pred_idx, target_idx);
-            let squared_diff_idx = ctx.mul(diff_idx, diff_idx);
+            // Squared error (y_pred - y_target)^2 of the single output
+            let (_, loss_idx) = mlp.train_step(&mut ctx, input, &[target],
+                                               loss::mse);
 
             // Store the squared difference for later summation
-            loss_terms.push(squared_diff_idx);
+            loss_terms.push(loss_idx);
         }
 
         // Sum all loss terms to get the total loss
@@ -55,7 +38,7 @@ fn main() {
         let loss_value     = ctx.value(total_loss_idx);
 
         // Backward pass: reset gradients and perform backpropagation
-        mlp.parameters().for_each(|p| ctx.clear_grad(p));
+        ctx.clear_grads_iter(mlp.parameters());
         ctx.backward(total_loss_idx);
 
         // Update parameters based on gradients
@@ -63,7 +46,7 @@ fn main() {
             // p.data += -learning_rate * p.grad
             let grad   = ctx.grad(param);
             let update = ctx.value(param) - learning_rate * grad;
-            (*ctx.get_mut(param)).data = update;
+            ctx.get_mut(param).data = update;
         }
 
         // Print epoch number and loss value
@@ -0,0 +1,88 @@
+//! The float functions the crate is built on. `core` has no `exp`, `ln`,
+//! `sqrt`, etc., so every call to them goes through here rather than the
+//! `std` float methods: with the `std` feature they forward to `std`, with
+//! only the `libm` feature (for `no_std` targets) to the `libm` crate
+
+#[cfg(not(any(feature = "std", feature = "libm")))]
+compile_error!("float math needs either the `std` or the `libm` feature");
+
+#[inline]
+pub(crate) fn exp(x: f64) -> f64 {
+    #[cfg(feature = "std")]
+    { x.exp() }
+    #[cfg(not(feature = "std"))]
+    { libm::exp(x) }
+}
+
+#[inline]
+pub(crate) fn ln(x: f64) -> f64 {
+    #[cfg(feature = "std")]
+    { x.ln() }
+    #[cfg(not(feature = "std"))]
+    { libm::log(x) }
+}
+
+/// `ln(1 + x)`, accurate for small `x`
+#[inline]
+pub(crate) fn ln_1p(x: f64) -> f64 {
+    #[cfg(feature = "std")]
+    { x.ln_1p() }
+    #[cfg(not(feature = "std"))]
+    { libm::log1p(x) }
+}
+
+#[inline]
+pub(crate) fn powf(x: f64, y: f64) -> f64 {
+    #[cfg(feature = "std")]
+    { x.powf(y) }
+    #[cfg(not(feature = "std"))]
+    { libm::pow(x, y) }
+}
+
+#[inline]
+pub(crate) fn powi(x: f64, n: i32) -> f64 {
+    #[cfg(feature = "std")]
+    { x.powi(n) }
+    #[cfg(not(feature = "std"))]
+    { libm::pow(x, n as f64) }
+}
+
+#[inline]
+pub(crate) fn sqrt(x: f64) -> f64 {
+    #[cfg(feature = "std")]
+    { x.sqrt() }
+    #[cfg(not(feature = "std"))]
+    { libm::sqrt(x) }
+}
+
+#[inline]
+pub(crate) fn cos(x: f64) -> f64 {
+    #[cfg(feature = "std")]
+    { x.cos() }
+    #[cfg(not(feature = "std"))]
+    { libm::cos(x) }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Context;
+
+    #[test]
+    fn tanh_and_exp_nodes() {
+        let mut ctx = Context::new();
+        // `(x, e^x, tanh(x))`
+        let cases = [(0.0, 1.0, 0.0),
+                     (1.0, core::f64::consts::E, 0.761_594_155_955_764_9),
+                     (-2.0, 0.135_335_283_236_612_7, -0.964_027_580_075_817)];
+        for (x, e, t) in cases {
+            let x = ctx.push(x);
+            let exp = ctx.exp(x);
+            let tanh = ctx.tanh(x);
+            assert!((ctx.value(exp) - e).abs() < 1e-15);
+            assert!((ctx.value(tanh) - t).abs() < 1e-15);
+
+            ctx.backward(tanh);
+            assert!((ctx[x].grad - (1.0 - t * t)).abs() < 1e-15);
+        }
+    }
+}
@@ -0,0 +1,31 @@
+use alloc::{vec, vec::Vec};
+
+/// Counts `(actual, predicted)` label pairs into an `n_classes x n_classes`
+/// matrix; rows are indexed by the actual label, columns by the prediction
+pub fn confusion_matrix(predicted: &[usize], actual: &[usize],
+                        n_classes: usize) -> Vec<Vec<usize>> {
+    assert_eq!(predicted.len(), actual.len(),
+               "predicted and actual labels differ in length");
+
+    let mut matrix = vec![vec![0; n_classes]; n_classes];
+    for (&p, &a) in predicted.iter().zip(actual.iter()) {
+        matrix[a][p] += 1;
+    }
+    matrix
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn confusion_matrix_counts_label_pairs() {
+        let actual    = [0, 0, 1, 1, 2, 2, 2];
+        let predicted = [0, 1, 1, 1, 2, 0, 2];
+
+        let matrix = confusion_matrix(&predicted, &actual, 3);
+        assert_eq!(matrix, vec![vec![1, 1, 0],
+                                vec![0, 2, 0],
+                                vec![1, 0, 2]]);
+    }
+}
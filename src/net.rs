@@ -1,19 +1,32 @@
+use alloc::{vec, vec::Vec};
 use core::num::NonZero;
-use crate::{Context, CtxIdx, Rng};
+use crate::{Context, CtxIdx, Rng, Init, Value, Activation};
 
 #[derive(Debug)]
 pub struct Neuron {
     weights: Vec<CtxIdx>,
-    bias: CtxIdx,
+    bias: Option<CtxIdx>,
 }
 
 impl Neuron {
     pub fn new(ctx: &mut Context, rng: &mut Rng,
                n_inputs: NonZero<usize>) -> Self {
-        let bias = ctx.push(rng.range(-1.0, 1.0));
-        let weights = (0..n_inputs.into())
-            .map(|_| ctx.push(rng.range(-1.0, 1.0)))
-            .collect();
+        Self::with_init(ctx, rng, n_inputs, Init::Uniform)
+    }
+
+    pub fn with_init(ctx: &mut Context, rng: &mut Rng,
+                     n_inputs: NonZero<usize>, init: Init) -> Self {
+        // A lone neuron is a layer of one
+        Self::build(ctx, rng, n_inputs, 1, init, true)
+    }
+
+    /// `fan_out` is the size of the layer the neuron belongs to
+    fn build(ctx: &mut Context, rng: &mut Rng, n_inputs: NonZero<usize>,
+             fan_out: usize, init: Init, bias: bool) -> Self {
+        let fan_in = n_inputs.into();
+        let mut sample = || ctx.push_param(init.sample(rng, fan_in, fan_out));
+        let bias = bias.then(&mut sample);
+        let weights = (0..fan_in).map(|_| sample()).collect();
 
         Self {
             weights,
@@ -21,37 +34,210 @@ impl Neuron {
         }
     }
 
-    pub fn forward(&self, ctx: &mut Context, x: &[CtxIdx]) -> CtxIdx {
+    /// A neuron over already pushed parameters
+    #[cfg(feature = "json")]
+    pub(crate) fn from_parts(weights: Vec<CtxIdx>,
+                             bias: Option<CtxIdx>) -> Self {
+        Self { weights, bias }
+    }
+
+    /// Overwrites the parameters with fresh samples (in the same order as
+    /// `with_init`) and zeroes their gradients, keeping their indices
+    pub fn reinitialize(&self, ctx: &mut Context, rng: &mut Rng, init: Init) {
+        self.resample(ctx, rng, init, 1);
+    }
+
+    fn resample(&self, ctx: &mut Context, rng: &mut Rng, init: Init,
+                fan_out: usize) {
+        let fan_in = self.fan_in();
+        let params = self.bias.into_iter()
+            .chain(self.weights.iter().copied());
+
+        for param in params {
+            let val = ctx.get_mut(param);
+            val.data = init.sample(rng, fan_in, fan_out);
+            val.grad = 0.0;
+        }
+    }
+
+    /// Number of inputs (weights) of this neuron
+    pub fn fan_in(&self) -> usize {
+        self.weights.len()
+    }
+
+    /// Copies this neuron's parameters from `src` into `dst`, returning a
+    /// neuron that refers to the copies
+    pub fn clone_into(&self, src: &Context, dst: &mut Context) -> Self {
+        let mut copy = |idx: CtxIdx| dst.push_val(Value {
+            grad: 0.0,
+            op: None,
+            ..src.get(idx).clone()
+        });
+
+        let bias = self.bias.map(&mut copy);
+        let weights = self.weights.iter().map(|&w| copy(w)).collect();
+
+        Self {
+            weights,
+            bias
+        }
+    }
+
+    pub fn weight_indices(&self) -> &[CtxIdx] {
+        &self.weights
+    }
+
+    /// `None` for neurons built without a bias
+    pub fn bias_index(&self) -> Option<CtxIdx> {
+        self.bias
+    }
+
+    /// The weighted sum of the inputs plus the bias, before the activation
+    fn pre_activation(&self, ctx: &mut Context, x: &[CtxIdx]) -> CtxIdx {
+        // Catches mismatched layer sizes that `zip` would silently truncate
+        debug_assert_eq!(x.len(), self.fan_in(),
+            "input has {} nodes but the neuron expects {}",
+            x.len(), self.fan_in());
+
         let mut act = self.bias;
         for (&wi, &xi) in self.weights.iter().zip(x.iter()) {
             let idx = ctx.mul(wi, xi);
-            act = ctx.add(act, idx);
+            act = Some(match act {
+                Some(act) => ctx.add(act, idx),
+                None      => idx,
+            });
+        }
+
+        // A bias-less neuron given no inputs sums to zero
+        act.unwrap_or_else(|| ctx.push(0.0))
+    }
+
+    pub fn forward(&self, ctx: &mut Context, x: &[CtxIdx]) -> CtxIdx {
+        self.forward_act(ctx, x, Activation::Tanh)
+    }
+
+    /// Like `forward`, but applies `act` instead of `tanh`
+    pub fn forward_act(&self, ctx: &mut Context, x: &[CtxIdx],
+                       act: Activation) -> CtxIdx {
+        let pre = self.pre_activation(ctx, x);
+        act.apply(ctx, pre)
+    }
+
+    /// Number of nodes `forward` pushes for an input of `n_inputs` nodes
+    pub fn forward_node_count(&self, n_inputs: usize) -> usize {
+        // One `mul` and one `add` per weight, plus the activation. Without a
+        // bias the first product starts the sum, saving one `add` (or, with
+        // no inputs at all, a zero constant stands in for the sum)
+        let n = self.weights.len().min(n_inputs);
+        match self.bias {
+            Some(_) => 2 * n + 1,
+            None    => (2 * n).max(2),
         }
-        ctx.tanh(act)
     }
 
     pub fn parameters(&self) -> impl Iterator<Item = CtxIdx> + '_ {
-        self.weights.iter().copied().chain(core::iter::once(self.bias))
+        self.weights.iter().copied().chain(self.bias)
     }
 }
 
 #[derive(Debug)]
 pub struct Layer {
     neurons: Vec<Neuron>,
+    activation: Activation,
 }
 
 impl Layer {
     pub fn new(ctx: &mut Context, rng: &mut Rng,
                n_inputs: NonZero<usize>, n_outputs: NonZero<usize>) -> Self {
-        let neurons = (0..n_outputs.into())
-            .map(|_| Neuron::new(ctx, rng, n_inputs))
+        Self::with_init(ctx, rng, n_inputs, n_outputs, Init::Uniform)
+    }
+
+    pub fn with_init(ctx: &mut Context, rng: &mut Rng,
+                     n_inputs: NonZero<usize>, n_outputs: NonZero<usize>,
+                     init: Init) -> Self {
+        Self::build(ctx, rng, (n_inputs, n_outputs), init, true,
+                    Activation::Tanh)
+    }
+
+    fn build(ctx: &mut Context, rng: &mut Rng,
+             (n_inputs, n_outputs): (NonZero<usize>, NonZero<usize>),
+             init: Init, bias: bool, activation: Activation) -> Self {
+        let fan_out = n_outputs.into();
+        let neurons = (0..fan_out)
+            .map(|_| Neuron::build(ctx, rng, n_inputs, fan_out, init, bias))
             .collect();
 
-        Self { neurons }
+        Self { neurons, activation }
+    }
+
+    #[cfg(feature = "json")]
+    pub(crate) fn from_parts(neurons: Vec<Neuron>,
+                             activation: Activation) -> Self {
+        Self { neurons, activation }
+    }
+
+    pub fn neurons(&self) -> &[Neuron] {
+        &self.neurons
+    }
+
+    /// Number of neurons, i.e. outputs, of this layer
+    pub fn size(&self) -> usize {
+        self.neurons.len()
+    }
+
+    /// The activation `forward` applies to every neuron
+    pub fn activation(&self) -> Activation {
+        self.activation
+    }
+
+    pub fn reinitialize(&self, ctx: &mut Context, rng: &mut Rng, init: Init) {
+        let fan_out = self.neurons.len();
+        for neuron in self.neurons.iter() {
+            neuron.resample(ctx, rng, init, fan_out);
+        }
+    }
+
+    pub fn clone_into(&self, src: &Context, dst: &mut Context) -> Self {
+        let neurons = self.neurons.iter()
+            .map(|n| n.clone_into(src, dst))
+            .collect();
+
+        Self { neurons, activation: self.activation }
     }
 
     pub fn forward(&self, ctx: &mut Context, x: &[CtxIdx]) -> Vec<CtxIdx> {
-        self.neurons.iter().map(|n| n.forward(ctx, x)).collect()
+        let activation = self.activation;
+        self.forward_with(ctx, x, |ctx, pre| activation.apply(ctx, pre))
+    }
+
+    /// Like `forward`, but applies `act` to each neuron's pre-activation
+    /// instead of the layer's own activation
+    pub fn forward_with<F>(&self, ctx: &mut Context, x: &[CtxIdx],
+                           act: F) -> Vec<CtxIdx>
+    where
+        F: Fn(&mut Context, CtxIdx) -> CtxIdx,
+    {
+        self.neurons.iter()
+            .map(|n| {
+                let pre = n.pre_activation(ctx, x);
+                act(ctx, pre)
+            })
+            .collect()
+    }
+
+    /// Like `forward`, but writes the outputs into `out` (cleared first) so
+    /// that its allocation can be reused
+    fn forward_into(&self, ctx: &mut Context, x: &[CtxIdx],
+                    out: &mut Vec<CtxIdx>) {
+        out.clear();
+        for neuron in self.neurons.iter() {
+            let pre = neuron.pre_activation(ctx, x);
+            out.push(self.activation.apply(ctx, pre));
+        }
+    }
+
+    pub fn forward_node_count(&self, n_inputs: usize) -> usize {
+        self.neurons.iter().map(|n| n.forward_node_count(n_inputs)).sum()
     }
 
     pub fn parameters(&self) -> impl Iterator<Item = CtxIdx> + '_ {
@@ -68,9 +254,60 @@ impl MultiLayerPerceptron {
     // the sizes of all the layers we want
     pub fn new(ctx: &mut Context, rng: &mut Rng,
                topology: &[NonZero<usize>]) -> Self {
+        Self::with_init(ctx, rng, topology, Init::Uniform)
+    }
 
-        let layers = topology.windows(2)
-            .map(|top| Layer::new(ctx, rng, top[0], top[1]))
+    /// Like `new`, but the hidden layers use `hidden` and the last layer
+    /// `output`, e.g. `Linear` for regression targets outside of `[-1, 1]`
+    pub fn new_with_output_activation(ctx: &mut Context, rng: &mut Rng,
+                                      topology: &[NonZero<usize>],
+                                      hidden: Activation,
+                                      output: Activation) -> Self {
+        let n_layers = topology.len().saturating_sub(1);
+        let layers = topology.windows(2).enumerate()
+            .map(|(i, top)| {
+                let last = i + 1 == n_layers;
+                let activation = if last { output } else { hidden };
+                Layer::build(ctx, rng, (top[0], top[1]), Init::Uniform, true,
+                             activation)
+            })
+            .collect();
+
+        Self { layers }
+    }
+
+    /// Creates a fresh context and rng and builds a model on them. Returns
+    /// `None` if any layer size in `topology` is zero
+    pub fn quick(topology: &[usize],
+                 seed: usize) -> Option<(Context, Rng, Self)> {
+        let topology = topology.iter()
+            .map(|&n| NonZero::new(n))
+            .collect::<Option<Vec<_>>>()?;
+
+        let mut ctx = Context::new();
+        let mut rng = Rng::new(seed);
+        let mlp = Self::new(&mut ctx, &mut rng, &topology);
+        Some((ctx, rng, mlp))
+    }
+
+    pub fn with_init(ctx: &mut Context, rng: &mut Rng,
+                     topology: &[NonZero<usize>], init: Init) -> Self {
+        let inits = vec![init; topology.len().saturating_sub(1)];
+        Self::with_layer_inits(ctx, rng, topology, &inits)
+    }
+
+    /// Like `with_init`, but layer `i` (between `topology[i]` and
+    /// `topology[i + 1]`) is initialized with `inits[i]`
+    pub fn with_layer_inits(ctx: &mut Context, rng: &mut Rng,
+                            topology: &[NonZero<usize>],
+                            inits: &[Init]) -> Self {
+        assert_eq!(inits.len(), topology.len().saturating_sub(1),
+                   "expected one init per layer");
+
+        let layers = topology.windows(2).zip(inits)
+            .map(|(top, &init)| {
+                Layer::with_init(ctx, rng, top[0], top[1], init)
+            })
             .collect();
 
         Self { layers }
@@ -81,16 +318,231 @@ impl MultiLayerPerceptron {
             .fold(x.to_vec(), |input, layer| layer.forward(ctx, &input))
     }
 
+    /// Resets every parameter in place with fresh samples from `init` and
+    /// zeroes their gradients; all parameter indices stay valid
+    pub fn reinitialize(&self, ctx: &mut Context, rng: &mut Rng, init: Init) {
+        self.layers.iter().for_each(|l| l.reinitialize(ctx, rng, init));
+    }
+
+    /// Copies all parameters from `src` into `dst`, returning a model that
+    /// runs on `dst` with identical weights
+    pub fn clone_into(&self, src: &Context, dst: &mut Context) -> Self {
+        let layers = self.layers.iter()
+            .map(|l| l.clone_into(src, dst))
+            .collect();
+
+        Self { layers }
+    }
+
+    /// Number of nodes `forward` pushes for an input of `n_inputs` nodes,
+    /// useful for sizing the context with `Context::reserve`
+    pub fn forward_node_count(&self, n_inputs: usize) -> usize {
+        self.layers.iter()
+            .fold((0, n_inputs), |(count, n_in), layer| {
+                (count + layer.forward_node_count(n_in), layer.neurons.len())
+            }).0
+    }
+
+    /// Like `forward`, but also returns every layer's activation indices
+    /// (the last one being the outputs)
+    pub fn forward_with_activations(&self, ctx: &mut Context, x: &[CtxIdx])
+            -> (Vec<CtxIdx>, Vec<Vec<CtxIdx>>) {
+        let mut activations: Vec<Vec<CtxIdx>> = Vec::new();
+        for layer in self.layers.iter() {
+            let input = activations.last().map_or(x, |a| a.as_slice());
+            activations.push(layer.forward(ctx, input));
+        }
+
+        let outputs = activations.last().cloned().unwrap_or_else(|| x.to_vec());
+        (outputs, activations)
+    }
+
+    /// Pushes `inputs`, runs a forward pass and builds `loss_fn(outputs,
+    /// target)` (e.g. `loss::mse`), returning the outputs and the loss index
+    /// ready for `backward`
+    pub fn train_step<F>(&self, ctx: &mut Context, inputs: &[f64],
+                         target: &[f64], loss_fn: F) -> (Vec<CtxIdx>, CtxIdx)
+    where
+        F: FnOnce(&mut Context, &[CtxIdx], &[f64]) -> CtxIdx,
+    {
+        let x = ctx.push_many(inputs);
+        let outputs = self.forward(ctx, &x);
+        let loss = loss_fn(ctx, &outputs, target);
+        (outputs, loss)
+    }
+
+    /// Forward pass with (inverted) dropout on every hidden layer: each
+    /// hidden unit is dropped with probability `p`, drawing once per unit
+    /// from `rng`, and the kept ones are scaled by `1 / (1 - p)`. Returns the
+    /// outputs and the mask of kept units, hidden layers concatenated in
+    /// order. A dropped unit is multiplied by 0, so its gradient is exactly 0
+    pub fn forward_dropout(&self, ctx: &mut Context, x: &[CtxIdx], p: f64,
+                           rng: &mut Rng) -> (Vec<CtxIdx>, Vec<bool>) {
+        assert!((0.0..1.0).contains(&p), "dropout probability not in [0, 1)");
+
+        let scale = 1.0 / (1.0 - p);
+        let mut mask = Vec::new();
+        let mut input = x.to_vec();
+        let (last, hidden) = match self.layers.split_last() {
+            Some(split) => split,
+            None        => return (input, mask),
+        };
+
+        for layer in hidden {
+            input = layer.forward(ctx, &input).into_iter()
+                .map(|unit| {
+                    let keep = rng.rand() >= p;
+                    mask.push(keep);
+                    let factor = ctx.push(if keep { scale } else { 0.0 });
+                    ctx.mul(unit, factor)
+                })
+                .collect();
+        }
+
+        (last.forward(ctx, &input), mask)
+    }
+
+    /// Forward pass for networks with a single output neuron, returning its
+    /// index directly. The hidden layers' outputs alternate between two
+    /// scratch buffers, so this allocates at most twice however deep the
+    /// network is, and not at all without hidden layers
+    pub fn forward_scalar(&self, ctx: &mut Context, x: &[CtxIdx]) -> CtxIdx {
+        let (last, hidden) = self.layers.split_last()
+            .expect("network has no layers");
+        assert_eq!(last.neurons.len(), 1,
+                   "forward_scalar requires a single output neuron");
+
+        let Some((first, rest)) = hidden.split_first() else {
+            let pre = last.neurons[0].pre_activation(ctx, x);
+            return last.activation.apply(ctx, pre);
+        };
+
+        let mut input = Vec::new();
+        let mut output = Vec::new();
+        first.forward_into(ctx, x, &mut input);
+        for layer in rest {
+            layer.forward_into(ctx, &input, &mut output);
+            core::mem::swap(&mut input, &mut output);
+        }
+
+        let pre = last.neurons[0].pre_activation(ctx, &input);
+        last.activation.apply(ctx, pre)
+    }
+
+    #[cfg(feature = "json")]
+    pub(crate) fn from_layers(layers: Vec<Layer>) -> Self {
+        Self { layers }
+    }
+
+    pub(crate) fn layers(&self) -> &[Layer] {
+        &self.layers
+    }
+
+    pub fn num_layers(&self) -> usize {
+        self.layers.len()
+    }
+
+    /// Layer `i`, the first one being fed the inputs
+    pub fn layer(&self, i: usize) -> Option<&Layer> {
+        self.layers.get(i)
+    }
+
     pub fn parameters(&self) -> impl Iterator<Item = CtxIdx> + '_ {
         self.layers.iter().flat_map(|layer| layer.parameters())
     }
 
+    /// Euclidean norm of each layer's parameter gradients, in layer order
+    pub fn grad_norms(&self, ctx: &Context) -> Vec<f64> {
+        self.layers.iter()
+            .map(|layer| {
+                let params: Vec<CtxIdx> = layer.parameters().collect();
+                ctx.grad_norm(&params)
+            })
+            .collect()
+    }
+
+    /// Rescales the gradients of every layer whose `grad_norms` entry
+    /// exceeds `max_norm` down to exactly `max_norm`; other layers are left
+    /// untouched
+    pub fn clip_grad_norm_per_layer(&self, ctx: &mut Context, max_norm: f64) {
+        for (layer, norm) in self.layers.iter().zip(self.grad_norms(ctx)) {
+            if norm <= max_norm { continue; }
+
+            let params: Vec<CtxIdx> = layer.parameters().collect();
+            ctx.scale_param_grads(&params, max_norm / norm);
+        }
+    }
+
+    /// Clears `requires_grad` on every parameter of layer `layer_idx`, so
+    /// optimizers leave the layer untouched (e.g. while fine-tuning)
+    pub fn freeze_layer(&self, ctx: &mut Context, layer_idx: usize) {
+        self.set_layer_requires_grad(ctx, layer_idx, false);
+    }
+
+    /// Undoes `freeze_layer`
+    pub fn unfreeze_layer(&self, ctx: &mut Context, layer_idx: usize) {
+        self.set_layer_requires_grad(ctx, layer_idx, true);
+    }
+
+    fn set_layer_requires_grad(&self, ctx: &mut Context, layer_idx: usize,
+                               requires_grad: bool) {
+        for param in self.layers[layer_idx].parameters() {
+            ctx.get_mut(param).requires_grad = requires_grad;
+        }
+    }
+
+    /// Zeroes and freezes (clears `requires_grad` on) every weight with
+    /// `|data| < threshold`, returning how many were pruned. Biases are kept
+    pub fn prune(&self, ctx: &mut Context, threshold: f64) -> usize {
+        let weights = self.layers.iter()
+            .flat_map(|layer| layer.neurons.iter())
+            .flat_map(|neuron| neuron.weights.iter());
+
+        let mut pruned = 0;
+        for &weight in weights {
+            let val = ctx.get_mut(weight);
+            if val.data.abs() < threshold {
+                val.data = 0.0;
+                val.requires_grad = false;
+                pruned += 1;
+            }
+        }
+        pruned
+    }
+
+    /// Buckets every weight (biases excluded) into `bins` equal-width bins
+    /// over `range`; values outside the range count towards the edge bins
+    pub fn weight_histogram(&self, ctx: &Context, bins: usize,
+                            (min, max): (f64, f64)) -> Vec<usize> {
+        assert!(bins > 0 && min < max, "invalid histogram shape");
+
+        let mut histogram = vec![0; bins];
+        let width = (max - min) / bins as f64;
+        let weights = self.layers.iter()
+            .flat_map(|layer| layer.neurons.iter())
+            .flat_map(|neuron| neuron.weights.iter());
+
+        for &weight in weights {
+            // Negative offsets saturate to 0 in the cast
+            let bin = ((ctx.value(weight) - min) / width) as usize;
+            histogram[bin.min(bins - 1)] += 1;
+        }
+        histogram
+    }
+
+    /// Prints every parameter with its gradient to stdout
+    #[cfg(feature = "std")]
     pub fn pretty_print(&self, ctx: &Context) {
         for (layer_idx, layer) in self.layers.iter().enumerate() {
             println!("Layer {layer_idx}:");
             for (neuron_idx, neuron) in layer.neurons.iter().enumerate() {
                 println!("    Neuron {neuron_idx}:");
-                println!("        Bias: {:>28.25?}", ctx.value(neuron.bias));
+                match neuron.bias {
+                    Some(bias) => {
+                        println!("        Bias: {:>28.25?}", ctx.value(bias))
+                    },
+                    None => println!("        Bias: none"),
+                }
                 println!("        Weights:");
                 for &weight in neuron.weights.iter() {
                     print!("            value: {:>23.20?}", ctx.value(weight));
@@ -100,3 +552,574 @@ impl MultiLayerPerceptron {
         }
     }
 }
+
+/// Several independently seeded models whose predictions are averaged
+#[derive(Debug)]
+pub struct Ensemble {
+    members: Vec<MultiLayerPerceptron>,
+}
+
+impl Ensemble {
+    /// Builds `count` models, the `i`th one seeded with `base_seed + i`
+    pub fn new(ctx: &mut Context, base_seed: usize, count: NonZero<usize>,
+               topology: &[NonZero<usize>]) -> Self {
+        let members = (0..count.into())
+            .map(|i| {
+                let mut rng = Rng::new(base_seed.wrapping_add(i));
+                MultiLayerPerceptron::new(ctx, &mut rng, topology)
+            })
+            .collect();
+
+        Self { members }
+    }
+
+    pub fn members(&self) -> &[MultiLayerPerceptron] {
+        &self.members
+    }
+
+    /// Runs every member on `inputs` and averages their outputs
+    pub fn predict_mean(&self, ctx: &mut Context, inputs: &[f64]) -> Vec<f64> {
+        let x = ctx.push_many(inputs);
+
+        let mut mean = Vec::new();
+        for member in self.members.iter() {
+            let out = member.forward(ctx, &x);
+            mean.resize(out.len(), 0.0);
+            for (m, v) in mean.iter_mut().zip(ctx.values(&out)) {
+                *m += v;
+            }
+        }
+
+        let count = self.members.len() as f64;
+        mean.iter_mut().for_each(|m| *m /= count);
+        mean
+    }
+}
+
+/// Step-by-step construction of a `MultiLayerPerceptron` whose layers may
+/// use different activations
+#[derive(Debug, Clone)]
+pub struct MlpBuilder {
+    n_inputs: NonZero<usize>,
+    layers: Vec<(NonZero<usize>, Activation, Option<Init>)>,
+    init: Init,
+    bias: bool,
+}
+
+impl MlpBuilder {
+    /// Starts a network taking `n_inputs` inputs, with no layers yet,
+    /// `Init::Uniform` weights and biases enabled
+    pub fn new(n_inputs: NonZero<usize>) -> Self {
+        Self {
+            n_inputs,
+            layers: Vec::new(),
+            init: Init::Uniform,
+            bias: true,
+        }
+    }
+
+    /// Appends a layer of `size` neurons
+    pub fn layer(mut self, size: NonZero<usize>,
+                 activation: Activation) -> Self {
+        self.layers.push((size, activation, None));
+        self
+    }
+
+    /// Like `layer`, but initializes this layer with `init` instead of the
+    /// builder-wide one
+    pub fn layer_with_init(mut self, size: NonZero<usize>,
+                           activation: Activation, init: Init) -> Self {
+        self.layers.push((size, activation, Some(init)));
+        self
+    }
+
+    /// The init of every layer not added through `layer_with_init`
+    pub fn init(mut self, init: Init) -> Self {
+        self.init = init;
+        self
+    }
+
+    /// Whether neurons get a bias parameter
+    pub fn bias(mut self, bias: bool) -> Self {
+        self.bias = bias;
+        self
+    }
+
+    /// Pushes the parameters into `ctx`. With all-`Tanh` layers and biases
+    /// this draws from `rng` exactly like `MultiLayerPerceptron::with_init`
+    pub fn build(&self, ctx: &mut Context,
+                 rng: &mut Rng) -> MultiLayerPerceptron {
+        let mut n_inputs = self.n_inputs;
+        let mut layers = Vec::with_capacity(self.layers.len());
+        for &(size, activation, init) in self.layers.iter() {
+            let init = init.unwrap_or(self.init);
+            layers.push(Layer::build(ctx, rng, (n_inputs, size), init,
+                                     self.bias, activation));
+            n_inputs = size;
+        }
+
+        MultiLayerPerceptron { layers }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn topology(sizes: &[usize]) -> Vec<NonZero<usize>> {
+        sizes.iter().map(|&n| NonZero::new(n).unwrap()).collect()
+    }
+
+    #[test]
+    fn forward_scalar_matches_forward() {
+        let mut ctx = Context::new();
+        let mut rng = Rng::new(1);
+        let mlp = MultiLayerPerceptron::new(&mut ctx, &mut rng,
+                                            &topology(&[3, 4, 4, 1]));
+        let x = ctx.push_many(&[2.0, 3.0, -1.0]);
+
+        let outputs = mlp.forward(&mut ctx, &x);
+        let scalar  = mlp.forward_scalar(&mut ctx, &x);
+        assert_eq!(ctx.value(scalar), ctx.value(outputs[0]));
+    }
+
+    #[test]
+    fn forward_node_count_is_exact() {
+        let mut ctx = Context::new();
+        let mut rng = Rng::new(1);
+        let mlp = MultiLayerPerceptron::new(&mut ctx, &mut rng,
+                                            &topology(&[3, 4, 4, 1]));
+        let x = ctx.push_many(&[2.0, 3.0, -1.0]);
+
+        let before = ctx.len();
+        mlp.forward(&mut ctx, &x);
+        assert_eq!(ctx.len() - before, mlp.forward_node_count(3));
+    }
+
+    #[test]
+    fn ensemble_of_identical_models_matches_one() {
+        let top = topology(&[3, 4, 2]);
+        let mut ctx = Context::new();
+        let members = (0..3)
+            .map(|_| MultiLayerPerceptron::new(&mut ctx, &mut Rng::new(5),
+                                               &top))
+            .collect();
+        let ensemble = Ensemble { members };
+        let single = MultiLayerPerceptron::new(&mut ctx, &mut Rng::new(5),
+                                               &top);
+
+        let inputs = [0.5, -1.0, 2.0];
+        let x = ctx.push_many(&inputs);
+        let out = single.forward(&mut ctx, &x);
+        let expected: Vec<f64> = ctx.values(&out).collect();
+
+        let mean = ensemble.predict_mean(&mut ctx, &inputs);
+        for (m, e) in mean.iter().zip(expected) {
+            assert!((m - e).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn ensemble_members_are_seeded_apart() {
+        let mut ctx = Context::new();
+        let ensemble = Ensemble::new(&mut ctx, 5, NonZero::new(2).unwrap(),
+                                     &topology(&[3, 1]));
+        let [a, b] = ensemble.members() else { panic!("expected 2 members") };
+
+        // Member `i` is seeded with `base_seed + i`
+        let seeded = MultiLayerPerceptron::new(&mut ctx, &mut Rng::new(6),
+                                               &topology(&[3, 1]));
+        let params = |m: &MultiLayerPerceptron| -> Vec<f64> {
+            m.parameters().map(|p| ctx.value(p)).collect()
+        };
+        assert_eq!(params(b), params(&seeded));
+        assert_ne!(params(a), params(b));
+    }
+
+    #[test]
+    fn fan_in_is_the_weight_count() {
+        let mut ctx = Context::new();
+        let neuron = Neuron::with_init(&mut ctx, &mut Rng::new(1),
+                                       NonZero::new(5).unwrap(),
+                                       Init::KaimingUniform);
+        assert_eq!(neuron.fan_in(), 5);
+
+        let bound = Init::KaimingUniform.bound(5, 1);
+        assert!(neuron.parameters().all(|p| ctx.value(p).abs() <= bound));
+    }
+
+    #[test]
+    fn neuron_exposes_its_parameters() {
+        let mut ctx = Context::new();
+        let neuron = Neuron::new(&mut ctx, &mut Rng::new(1),
+                                 NonZero::new(4).unwrap());
+
+        assert_eq!(neuron.weight_indices().len(), 4);
+        let bias = neuron.bias_index().unwrap();
+        assert!(ctx.get(bias).requires_grad);
+        assert!(!neuron.weight_indices().contains(&bias));
+    }
+
+    #[test]
+    fn pruned_weights_survive_an_optimizer_step() {
+        use crate::{Optimizer, Sgd};
+
+        let (mut ctx, _, mlp) = MultiLayerPerceptron::quick(&[3, 4, 1], 2)
+            .unwrap();
+        let weights: Vec<CtxIdx> = mlp.layers.iter()
+            .flat_map(|l| l.neurons.iter())
+            .flat_map(|n| n.weight_indices().iter().copied())
+            .collect();
+        let small: Vec<CtxIdx> = weights.iter().copied()
+            .filter(|&w| ctx.value(w).abs() < 0.5)
+            .collect();
+        assert!(!small.is_empty());
+        assert_eq!(mlp.prune(&mut ctx, 0.5), small.len());
+
+        let (_, loss) = mlp.train_step(&mut ctx, &[1.0, -1.0, 0.5], &[2.0],
+                                       crate::loss::mse);
+        ctx.backward(loss);
+        let params: Vec<CtxIdx> = mlp.parameters().collect();
+        Sgd::new(0.1).step(&mut ctx, &params);
+
+        assert!(small.iter().all(|&w| ctx.value(w) == 0.0));
+        assert!(small.iter().any(|&w| ctx.grad(w) != 0.0));
+    }
+
+    #[test]
+    fn weight_histogram_clamps_into_edge_bins() {
+        let (mut ctx, _, mlp) = MultiLayerPerceptron::quick(&[2, 3], 1)
+            .unwrap();
+        let weights = [-5.0, -0.75, -0.5, 0.1, 0.9, 7.0];
+        let neurons = mlp.layers[0].neurons();
+        let indices = neurons.iter().flat_map(|n| n.weight_indices());
+        for (&idx, &w) in indices.zip(weights.iter()) {
+            ctx.get_mut(idx).data = w;
+        }
+        for bias in neurons.iter().filter_map(Neuron::bias_index) {
+            ctx.get_mut(bias).data = 0.0;
+        }
+
+        // Biases would land in bin 2 if they were counted
+        assert_eq!(mlp.weight_histogram(&ctx, 4, (-1.0, 1.0)), [2, 1, 1, 2]);
+    }
+
+    #[test]
+    fn clone_into_reproduces_outputs() {
+        let (mut src, _, mlp) = MultiLayerPerceptron::quick(&[3, 4, 2], 8)
+            .unwrap();
+        let mut dst = Context::new();
+        dst.push(123.0);
+        let copy = mlp.clone_into(&src, &mut dst);
+        assert_eq!(dst.len(), 1 + src.len());
+
+        let inputs = [0.3, -0.7, 1.5];
+        let x = src.push_many(&inputs);
+        let out = mlp.forward(&mut src, &x);
+        let x = dst.push_many(&inputs);
+        let copied = copy.forward(&mut dst, &x);
+        assert_eq!(src.values(&out).collect::<Vec<_>>(),
+                   dst.values(&copied).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn forward_with_activations_matches_topology() {
+        let (mut ctx, _, mlp) = MultiLayerPerceptron::quick(&[3, 4, 5, 2], 4)
+            .unwrap();
+        let x = ctx.push_many(&[1.0, 0.0, -1.0]);
+
+        let (outputs, activations) = mlp.forward_with_activations(&mut ctx, &x);
+        let sizes: Vec<usize> = activations.iter().map(Vec::len).collect();
+        assert_eq!(sizes, [4, 5, 2]);
+        assert_eq!(activations.last(), Some(&outputs));
+
+        let expected = mlp.forward(&mut ctx, &x);
+        assert_eq!(ctx.values(&outputs).collect::<Vec<_>>(),
+                   ctx.values(&expected).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn forward_with_applies_the_closure() {
+        let mut ctx = Context::new();
+        let layer = Layer::new(&mut ctx, &mut Rng::new(3),
+                               NonZero::new(3).unwrap(),
+                               NonZero::new(6).unwrap());
+        let x = ctx.push_many(&[1.0, -2.0, 0.5]);
+
+        let tanh = layer.forward_with(&mut ctx, &x, |ctx, pre| ctx.tanh(pre));
+        let own  = layer.forward(&mut ctx, &x);
+        assert_eq!(ctx.values(&tanh).collect::<Vec<_>>(),
+                   ctx.values(&own).collect::<Vec<_>>());
+
+        // A ReLU built from existing ops: the pre-activation times 0 or 1
+        let relu = |ctx: &mut Context, pre: CtxIdx| {
+            let gate = ctx.push(if ctx.value(pre) > 0.0 { 1.0 } else { 0.0 });
+            ctx.mul(pre, gate)
+        };
+        let pre   = layer.forward_with(&mut ctx, &x, |_, pre| pre);
+        let relus = layer.forward_with(&mut ctx, &x, relu);
+        for (p, r) in ctx.values(&pre).zip(ctx.values(&relus)) {
+            assert_eq!(r, p.max(0.0));
+        }
+    }
+
+    #[test]
+    fn reinitialize_keeps_indices() {
+        let (mut ctx, mut rng, mlp) = MultiLayerPerceptron::quick(&[3, 4, 1], 6)
+            .unwrap();
+        let indices: Vec<CtxIdx> = mlp.parameters().collect();
+        let before: Vec<f64> = ctx.values(&indices).collect();
+        indices.iter().for_each(|&p| ctx.get_mut(p).grad = 1.0);
+        let len = ctx.len();
+
+        mlp.reinitialize(&mut ctx, &mut rng, Init::KaimingUniform);
+        assert_eq!(ctx.len(), len);
+        assert_eq!(mlp.parameters().collect::<Vec<_>>(), indices);
+        assert!(indices.iter().all(|&p| ctx.grad(p) == 0.0));
+
+        let after: Vec<f64> = ctx.values(&indices).collect();
+        assert!(before.iter().zip(&after).all(|(b, a)| b != a));
+    }
+
+    #[test]
+    fn quick_rejects_zero_sized_layers() {
+        assert!(MultiLayerPerceptron::quick(&[3, 0, 1], 1).is_none());
+
+        let (ctx, _, mlp) = MultiLayerPerceptron::quick(&[3, 4, 1], 1)
+            .unwrap();
+        assert_eq!(mlp.num_layers(), 2);
+        assert_eq!(mlp.parameters().count(), ctx.len());
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "input has 2 nodes but the neuron expects 3")]
+    fn layer_rejects_wrong_input_length() {
+        let mut ctx = Context::new();
+        let layer = Layer::new(&mut ctx, &mut Rng::new(1),
+                               NonZero::new(3).unwrap(),
+                               NonZero::new(2).unwrap());
+        let x = ctx.push_many(&[1.0, 2.0]);
+        layer.forward(&mut ctx, &x);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "input has 4 nodes but the neuron expects 3")]
+    fn forward_scalar_rejects_wrong_input_length() {
+        let (mut ctx, _, mlp) = MultiLayerPerceptron::quick(&[3, 1], 1)
+            .unwrap();
+        let x = ctx.push_many(&[1.0, 2.0, 3.0, 4.0]);
+        mlp.forward_scalar(&mut ctx, &x);
+    }
+
+    #[test]
+    fn builder_matches_new() {
+        let top = topology(&[3, 4, 4, 1]);
+        let mut ctx = Context::new();
+        let built = top[1..].iter()
+            .fold(MlpBuilder::new(top[0]), |b, &size| {
+                b.layer(size, Activation::Tanh)
+            })
+            .build(&mut ctx, &mut Rng::new(13));
+        let new = MultiLayerPerceptron::new(&mut ctx, &mut Rng::new(13), &top);
+
+        let x = ctx.push_many(&[2.0, 3.0, -1.0]);
+        let a = built.forward(&mut ctx, &x);
+        let b = new.forward(&mut ctx, &x);
+        assert_eq!(ctx.value(a[0]), ctx.value(b[0]));
+
+        let bare = MlpBuilder::new(top[0])
+            .layer(top[1], Activation::Linear)
+            .bias(false)
+            .build(&mut ctx, &mut Rng::new(13));
+        let neurons = bare.layer(0).unwrap().neurons();
+        assert!(neurons.iter().all(|n| n.bias_index().is_none()));
+        assert_eq!(bare.parameters().count(), 3 * 4);
+    }
+
+    #[test]
+    fn layer_inits_apply_per_layer() {
+        let mut ctx = Context::new();
+        let inits = [Init::KaimingUniform, Init::XavierNormal];
+        let model = MultiLayerPerceptron::with_layer_inits(
+            &mut ctx, &mut Rng::new(17), &topology(&[100, 100, 100]), &inits);
+
+        let values = |layer: usize| -> Vec<f64> {
+            model.layer(layer).unwrap().parameters()
+                .map(|p| ctx.value(p))
+                .collect()
+        };
+
+        let uniform = values(0);
+        let bound = Init::KaimingUniform.bound(100, 100);
+        assert!(uniform.iter().all(|w| w.abs() <= bound));
+        assert!(uniform.iter().any(|w| w.abs() > 0.99 * bound));
+
+        // Normal samples have a standard deviation of the bound, and unlike
+        // the uniform layer's, some land well outside of it
+        let normal = values(1);
+        let std = Init::XavierNormal.bound(100, 100);
+        let var = normal.iter().map(|w| w * w).sum::<f64>()
+            / normal.len() as f64;
+        assert!((var.sqrt() / std - 1.0).abs() < 0.05);
+        assert!(normal.iter().any(|w| w.abs() > 3.0 * std));
+    }
+
+    #[test]
+    fn train_step_loss_matches_manual_mse() {
+        let mut ctx = Context::new();
+        let model = MultiLayerPerceptron::new(&mut ctx, &mut Rng::new(4),
+                                              &topology(&[3, 4, 2]));
+        let target = [0.5, -0.25];
+        let (outputs, loss) = model.train_step(
+            &mut ctx, &[1.0, -2.0, 0.5], &target, crate::loss::mse);
+
+        let manual = ctx.values(&outputs).zip(target)
+            .map(|(y, t)| (y - t) * (y - t))
+            .sum::<f64>() / target.len() as f64;
+        assert!((ctx.value(loss) - manual).abs() < 1e-12);
+
+        ctx.backward(loss);
+        assert!(model.parameters().any(|p| ctx.grad(p) != 0.0));
+    }
+
+    #[test]
+    fn dropout_mask_is_reproducible_and_zeroes_dropped_grads() {
+        let mut ctx = Context::new();
+        let model = MultiLayerPerceptron::new(&mut ctx, &mut Rng::new(8),
+                                              &topology(&[3, 8, 8, 1]));
+        let x = ctx.push_many(&[0.5, -1.0, 2.0]);
+
+        let (_, first) = model.forward_dropout(&mut ctx, &x, 0.5,
+                                               &mut Rng::new(21));
+        let (out, mask) = model.forward_dropout(&mut ctx, &x, 0.5,
+                                                &mut Rng::new(21));
+        assert_eq!(mask, first);
+        assert_eq!(mask.len(), 16);
+        assert!(mask.contains(&true) && mask.contains(&false));
+
+        // A dropped unit's only path to the output is multiplied by 0
+        ctx.backward(out[0]);
+        let hidden = model.layers()[..2].iter().flat_map(Layer::neurons);
+        for (neuron, &keep) in hidden.zip(&mask) {
+            if !keep {
+                assert!(neuron.parameters().all(|p| ctx.grad(p) == 0.0));
+            }
+        }
+    }
+
+    #[test]
+    fn per_layer_clipping_only_scales_large_layers() {
+        let mut ctx = Context::new();
+        let model = MultiLayerPerceptron::new(&mut ctx, &mut Rng::new(2),
+                                              &topology(&[2, 3, 1]));
+        for (layer, grad) in model.layers().iter().zip([10.0, 0.01]) {
+            for p in layer.parameters() {
+                ctx[p].grad = grad;
+            }
+        }
+
+        let before = model.grad_norms(&ctx);
+        model.clip_grad_norm_per_layer(&mut ctx, 1.0);
+        let after = model.grad_norms(&ctx);
+
+        assert!(before[0] > 1.0 && (after[0] - 1.0).abs() < 1e-12);
+        assert_eq!(after[1], before[1]);
+        assert!(model.layers()[1].parameters().all(|p| ctx.grad(p) == 0.01));
+    }
+
+    #[test]
+    fn linear_output_fits_targets_beyond_tanh_range() {
+        use crate::{Optimizer, Sgd};
+
+        let fit = |output| {
+            let mut ctx = Context::new();
+            let model = MultiLayerPerceptron::new_with_output_activation(
+                &mut ctx, &mut Rng::new(6), &topology(&[1, 4, 1]),
+                Activation::Tanh, output);
+            let params: Vec<CtxIdx> = model.parameters().collect();
+            let mark = ctx.len();
+
+            let mut opt = Sgd::new(0.01);
+            for _ in 0..500 {
+                let x = ctx.push(1.0);
+                let pred = model.forward_scalar(&mut ctx, &[x]);
+                let loss = crate::loss::mse(&mut ctx, &[pred], &[10.0]);
+                ctx.backward(loss);
+                opt.step(&mut ctx, &params);
+                ctx.truncate(mark);
+            }
+
+            let x = ctx.push(1.0);
+            let pred = model.forward_scalar(&mut ctx, &[x]);
+            ctx.value(pred)
+        };
+
+        assert!((fit(Activation::Linear) - 10.0).abs() < 1e-3);
+        assert!(fit(Activation::Tanh) < 1.0);
+    }
+
+    #[test]
+    fn forward_act_applies_the_given_activation() {
+        let mut ctx = Context::new();
+        let n_inputs = NonZero::new(2).unwrap();
+        let neuron = Neuron::new(&mut ctx, &mut Rng::new(1), n_inputs);
+        for (p, val) in neuron.parameters().zip([0.5, -1.0, 0.25]) {
+            ctx[p].data = val;
+        }
+        let x = ctx.push_many(&[2.0, 4.0]);
+
+        // `parameters` yields the weights, then the bias
+        let pre = 0.5 * 2.0 - 1.0 * 4.0 + 0.25;
+        let linear = neuron.forward_act(&mut ctx, &x, Activation::Linear);
+        assert_eq!(ctx.value(linear), pre);
+
+        let tanh = neuron.forward_act(&mut ctx, &x, Activation::Tanh);
+        let plain = neuron.forward(&mut ctx, &x);
+        assert_eq!(ctx.value(tanh), ctx.value(plain));
+        assert!((ctx.value(tanh) - pre.tanh()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn layer_accessors_match_the_example_topology() {
+        let (_, _, model) = MultiLayerPerceptron::quick(&[3, 4, 4, 1], 0)
+            .unwrap();
+        assert_eq!(model.num_layers(), 3);
+
+        let sizes: Vec<_> = (0..model.num_layers())
+            .map(|i| model.layer(i).unwrap().size())
+            .collect();
+        assert_eq!(sizes, [4, 4, 1]);
+        assert_eq!(model.layer(0).unwrap().neurons()[0].fan_in(), 3);
+        assert!(model.layer(3).is_none());
+    }
+
+    #[test]
+    fn frozen_layers_survive_an_optimizer_step() {
+        use crate::{Optimizer, Sgd};
+
+        let mut ctx = Context::new();
+        let model = MultiLayerPerceptron::new(&mut ctx, &mut Rng::new(12),
+                                              &topology(&[2, 3, 1]));
+        let params: Vec<CtxIdx> = model.parameters().collect();
+        let layer_values = |ctx: &Context, i: usize| -> Vec<f64> {
+            model.layers()[i].parameters().map(|p| ctx.value(p)).collect()
+        };
+
+        model.freeze_layer(&mut ctx, 0);
+        let before = [layer_values(&ctx, 0), layer_values(&ctx, 1)];
+        let x = ctx.push_many(&[0.5, -1.5]);
+        let out = model.forward_scalar(&mut ctx, &x);
+        ctx.backward(out);
+        Sgd::new(0.1).step(&mut ctx, &params);
+
+        assert_eq!(layer_values(&ctx, 0), before[0]);
+        assert!(layer_values(&ctx, 1).iter().zip(&before[1])
+                .all(|(after, before)| after != before));
+
+        model.unfreeze_layer(&mut ctx, 0);
+        Sgd::new(0.1).step(&mut ctx, &params);
+        assert_ne!(layer_values(&ctx, 0), before[0]);
+    }
+}
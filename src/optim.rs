@@ -0,0 +1,306 @@
+use alloc::{collections::BTreeMap, vec::Vec};
+use crate::{Context, CtxIdx, MultiLayerPerceptron};
+
+/// An exponential moving average of a set of parameters (Polyak averaging)
+#[derive(Debug, Clone)]
+pub struct EmaShadow {
+    params: Vec<CtxIdx>,
+    shadow: Vec<f64>,
+    decay: f64,
+}
+
+impl EmaShadow {
+    /// Creates a shadow initialized to the current values of `params`
+    pub fn new(ctx: &Context, params: impl IntoIterator<Item = CtxIdx>,
+               decay: f64) -> Self {
+        let params: Vec<CtxIdx> = params.into_iter().collect();
+        let shadow = params.iter().map(|&p| ctx.value(p)).collect();
+
+        Self { params, shadow, decay }
+    }
+
+    /// `shadow = decay * shadow + (1 - decay) * data`, call after each step
+    pub fn update(&mut self, ctx: &Context) {
+        for (s, &p) in self.shadow.iter_mut().zip(self.params.iter()) {
+            *s = self.decay * *s + (1.0 - self.decay) * ctx.value(p);
+        }
+    }
+
+    pub fn shadow(&self) -> &[f64] {
+        &self.shadow
+    }
+
+    /// Overwrites the parameters in `ctx` with the averaged values
+    pub fn copy_to(&self, ctx: &mut Context) {
+        for (&s, &p) in self.shadow.iter().zip(self.params.iter()) {
+            ctx.get_mut(p).data = s;
+        }
+    }
+}
+
+/// An update rule applied to a group of parameters after `backward`.
+/// Parameters with `requires_grad` cleared (i.e. frozen) are left untouched
+pub trait Optimizer {
+    fn step(&mut self, ctx: &mut Context, params: &[CtxIdx]);
+}
+
+/// Stochastic gradient descent with optional (Nesterov) momentum
+#[derive(Debug, Clone)]
+pub struct Sgd {
+    pub lr: f64,
+    pub momentum: f64,
+    pub nesterov: bool,
+
+    /// Per-parameter velocities, created on a parameter's first step
+    velocity: BTreeMap<CtxIdx, f64>,
+}
+
+impl Sgd {
+    /// Plain SGD, without momentum
+    pub fn new(lr: f64) -> Self {
+        Self::with_momentum(lr, 0.0, false)
+    }
+
+    pub fn with_momentum(lr: f64, momentum: f64, nesterov: bool) -> Self {
+        Self { lr, momentum, nesterov, velocity: BTreeMap::new() }
+    }
+}
+
+impl Optimizer for Sgd {
+    fn step(&mut self, ctx: &mut Context, params: &[CtxIdx]) {
+        for &param in params {
+            if !ctx.get(param).requires_grad { continue; }
+
+            // v = momentum * v + grad
+            let grad = ctx.grad(param);
+            let v = self.velocity.entry(param).or_insert(0.0);
+            *v = self.momentum * *v + grad;
+
+            // Nesterov in its reformulated form: instead of evaluating the
+            // gradient at the look-ahead position `p - lr * momentum * v`,
+            // step along `grad + momentum * v`, which needs no extra forward
+            let direction = if self.nesterov {
+                grad + self.momentum * *v
+            } else {
+                *v
+            };
+
+            let update = ctx.value(param) - self.lr * direction;
+            ctx.get_mut(param).data = update;
+        }
+    }
+}
+
+/// Lookahead (Zhang et al.): lets `inner` take `k` fast steps, then moves
+/// the slow weights a fraction `alpha` towards the fast weights and resets
+/// the fast weights to them.
+///
+/// Every `step` call counts as one fast step, and a synchronization only
+/// touches the `params` of the call it happens in. Call `step` exactly once
+/// per iteration, with all of the parameters: stepping several groups
+/// separately counts every group's call, syncing after `k / groups`
+/// iterations and only the group whose call reaches `k`
+#[derive(Debug, Clone)]
+pub struct Lookahead<O: Optimizer> {
+    pub inner: O,
+    pub k: usize,
+    pub alpha: f64,
+
+    /// Slow weights, captured on a parameter's first step
+    slow: BTreeMap<CtxIdx, f64>,
+
+    /// Fast steps taken since the last synchronization
+    steps: usize,
+}
+
+impl<O: Optimizer> Lookahead<O> {
+    pub fn new(inner: O, k: usize, alpha: f64) -> Self {
+        Self { inner, k, alpha, slow: BTreeMap::new(), steps: 0 }
+    }
+
+    pub fn slow_weight(&self, param: CtxIdx) -> Option<f64> {
+        self.slow.get(&param).copied()
+    }
+}
+
+impl<O: Optimizer> Optimizer for Lookahead<O> {
+    fn step(&mut self, ctx: &mut Context, params: &[CtxIdx]) {
+        for &param in params {
+            self.slow.entry(param).or_insert_with(|| ctx.value(param));
+        }
+
+        self.inner.step(ctx, params);
+        self.steps += 1;
+        if self.steps < self.k { return; }
+        self.steps = 0;
+
+        // slow += alpha * (fast - slow); fast = slow
+        for &param in params {
+            if !ctx.get(param).requires_grad { continue; }
+            let slow = self.slow.get_mut(&param).unwrap();
+            *slow += self.alpha * (ctx.value(param) - *slow);
+            ctx.get_mut(param).data = *slow;
+        }
+    }
+}
+
+/// Gradient descent with a LARS-style trust ratio: each parameter group's
+/// step is scaled by `||weights|| / ||grads||`, so groups with large weights
+/// but small gradients take proportionally larger steps
+#[derive(Debug, Clone)]
+pub struct TrustRatio {
+    pub base_lr: f64,
+}
+
+impl TrustRatio {
+    pub fn new(base_lr: f64) -> Self {
+        Self { base_lr }
+    }
+
+    /// The factor the base learning rate is scaled by for `params`; falls
+    /// back to `1.0` when either norm is zero
+    pub fn ratio(ctx: &Context, params: &[CtxIdx]) -> f64 {
+        let weight_norm = ctx.value_norm(params);
+        let grad_norm   = ctx.grad_norm(params);
+
+        if weight_norm > 0.0 && grad_norm > 0.0 {
+            weight_norm / grad_norm
+        } else {
+            1.0
+        }
+    }
+
+    /// Steps every layer of `mlp` as its own parameter group
+    pub fn step_layers(&mut self, ctx: &mut Context,
+                       mlp: &MultiLayerPerceptron) {
+        for layer in mlp.layers() {
+            let params: Vec<CtxIdx> = layer.parameters().collect();
+            self.step(ctx, &params);
+        }
+    }
+}
+
+impl Optimizer for TrustRatio {
+    fn step(&mut self, ctx: &mut Context, params: &[CtxIdx]) {
+        let lr = self.base_lr * Self::ratio(ctx, params);
+
+        for &param in params {
+            if !ctx.get(param).requires_grad { continue; }
+            let update = ctx.value(param) - lr * ctx.grad(param);
+            ctx.get_mut(param).data = update;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ema_shadow_lags_parameters() {
+        let mut ctx = Context::new();
+        let p = ctx.push_param(0.0);
+        let mut ema = EmaShadow::new(&ctx, [p], 0.5);
+
+        // With the data held at 1, the shadow closes half the gap every step
+        ctx.get_mut(p).data = 1.0;
+        for n in 1..=3 {
+            ema.update(&ctx);
+            assert_eq!(ema.shadow(), &[1.0 - 0.5f64.powi(n)]);
+        }
+
+        ema.copy_to(&mut ctx);
+        assert_eq!(ctx.value(p), 0.875);
+    }
+
+    #[test]
+    fn trust_ratio_favors_large_weights_with_small_grads() {
+        let mut ctx = Context::new();
+        let big   = [ctx.push_param(10.0), ctx.push_param(-10.0)];
+        let small = [ctx.push_param(0.1)];
+        for (&p, grad) in big.iter().zip([0.1, -0.1]) {
+            ctx.get_mut(p).grad = grad;
+        }
+        ctx.get_mut(small[0]).grad = 1.0;
+
+        let mut opt = TrustRatio::new(0.01);
+        opt.step(&mut ctx, &big);
+        opt.step(&mut ctx, &small);
+
+        // Effective learning rate = step / gradient
+        let big_lr   = (10.0 - ctx.value(big[0])) / 0.1;
+        let small_lr = (0.1 - ctx.value(small[0])) / 1.0;
+        assert!((big_lr - 0.01 * 100.0).abs() < 1e-9);
+        assert!((small_lr - 0.01 * 0.1).abs() < 1e-9);
+        assert!(big_lr > small_lr);
+    }
+
+    /// Steps `opt` takes to minimize `sum(c_i * p_i^2 / 2)` from all-ones
+    /// parameters to within `1e-6`, with the gradients `c_i * p_i` set by hand
+    fn steps_to_minimize(opt: &mut impl Optimizer,
+                         curvatures: &[f64]) -> usize {
+        let mut ctx = Context::new();
+        let params: Vec<CtxIdx> = curvatures.iter()
+            .map(|_| ctx.push_param(1.0))
+            .collect();
+
+        (1..10_000)
+            .find(|_| {
+                for (&p, &c) in params.iter().zip(curvatures) {
+                    ctx.get_mut(p).grad = c * ctx.value(p);
+                }
+                opt.step(&mut ctx, &params);
+                params.iter().all(|&p| ctx.value(p).abs() < 1e-6)
+            })
+            .expect("the quadratic wasn't minimized")
+    }
+
+    #[test]
+    fn nesterov_trajectory() {
+        // Minimizing p^2 from p = 1: the velocity is 2, then 0.9 * 2 + 1.6
+        // (plain) or 0.9 * 2 + 1.24 (Nesterov, which also steps along the
+        // gradient plus the momentum-scaled velocity)
+        for (nesterov, expected) in [(false, [0.8, 0.46]),
+                                     (true, [0.62, 0.2224])] {
+            let mut ctx = Context::new();
+            let p = ctx.push_param(1.0);
+            let mut opt = Sgd::with_momentum(0.1, 0.9, nesterov);
+            for expected in expected {
+                ctx.get_mut(p).grad = 2.0 * ctx.value(p);
+                opt.step(&mut ctx, &[p]);
+                assert!((ctx.value(p) - expected).abs() < 1e-12);
+            }
+        }
+
+        // An ill-conditioned quadratic, where heavy plain momentum oscillates
+        let curvatures = [1.0, 10.0];
+        let mut plain    = Sgd::with_momentum(0.1, 0.9, false);
+        let mut nesterov = Sgd::with_momentum(0.1, 0.9, true);
+        let plain    = steps_to_minimize(&mut plain, &curvatures);
+        let nesterov = steps_to_minimize(&mut nesterov, &curvatures);
+        assert!(nesterov < plain, "{nesterov} >= {plain} steps");
+    }
+
+    #[test]
+    fn lookahead_syncs_every_k_steps() {
+        let mut ctx = Context::new();
+        let p = ctx.push_param(1.0);
+        let mut opt = Lookahead::new(Sgd::new(0.1), 3, 0.5);
+
+        // Fast steps of 0.1 each, the slow weight staying put
+        for expected in [0.9, 0.8] {
+            ctx.get_mut(p).grad = 1.0;
+            opt.step(&mut ctx, &[p]);
+            assert!((ctx.value(p) - expected).abs() < 1e-12);
+            assert_eq!(opt.slow_weight(p), Some(1.0));
+        }
+
+        // The third step reaches 0.7; the slow weight moves halfway there
+        // and the fast weight is reset to it
+        ctx.get_mut(p).grad = 1.0;
+        opt.step(&mut ctx, &[p]);
+        let slow = opt.slow_weight(p).unwrap();
+        assert!((slow - 0.85).abs() < 1e-12);
+        assert_eq!(ctx.value(p), slow);
+    }
+}
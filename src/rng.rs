@@ -1,4 +1,7 @@
+use crate::math;
+
 /// A deterministic PRNG (xorshift)
+#[derive(Debug, Clone)]
 pub struct Rng(usize);
 
 impl Rng {
@@ -12,18 +15,280 @@ impl Rng {
         rng
     }
 
-    /// Returns a pseudo-random (predetermined) number in the range [0.0, 1.0]
+    /// Returns a pseudo-random (predetermined) number in the range [0.0, 1.0)
     pub fn rand(&mut self) -> f64 {
         let ret = self.0;
         self.0 ^= self.0 << 13;
         self.0 ^= self.0 >> 17;
         self.0 ^= self.0 << 43;
-        ret as f64 / usize::MAX as f64
+
+        // xorshift* output scrambling; the raw state has weak low bits
+        let ret = (ret as u64).wrapping_mul(0x2545f4914f6cdd1d);
+
+        // Keep only the top 53 bits, which an `f64` represents exactly
+        (ret >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Advances the state by 2^32 draws.
+    ///
+    /// The xorshift step is linear over GF(2), i.e. a 64x64 bit matrix `T`,
+    /// so `T^(2^32)` is computed by squaring `T` 32 times and then applied to
+    /// the state
+    pub fn jump(&mut self) {
+        self.jump_pow2(32);
     }
 
-    /// Returns a pseudo-random (predetermined) number within a given range
+    /// Advances the state by `2^log2_steps` draws, see `jump`
+    fn jump_pow2(&mut self, log2_steps: u32) {
+        let step = |x: u64| {
+            let mut x = x;
+            x ^= x << 13;
+            x ^= x >> 17;
+            x ^= x << 43;
+            x
+        };
+        let apply = |m: &[u64; 64], x: u64| {
+            (0..64).filter(|&i| (x >> i) & 1 == 1)
+                .fold(0, |acc, i| acc ^ m[i])
+        };
+
+        // Column `i` holds the image of the unit vector `1 << i`
+        let mut matrix: [u64; 64] = core::array::from_fn(|i| step(1 << i));
+        for _ in 0..log2_steps {
+            matrix = core::array::from_fn(|i| apply(&matrix, matrix[i]));
+        }
+
+        self.0 = apply(&matrix, self.0 as u64) as usize;
+    }
+
+    /// Splits off an independent stream: the returned Rng continues the
+    /// current sequence while `self` jumps 2^32 draws ahead, so repeated
+    /// forks hand out non-overlapping blocks of the sequence
+    pub fn fork(&mut self) -> Rng {
+        let child = Rng(self.0);
+        self.jump();
+        child
+    }
+
+    /// Returns a pseudo-random (predetermined) number in the range
+    /// [min, max); requires `min <= max`, `min == max` always returns `min`
     pub fn range(&mut self, min: f64, max: f64) -> f64 {
+        debug_assert!(min <= max, "inverted range [{min}, {max})");
         let scale = max - min;
         (self.rand() * scale) + min
     }
+
+    /// Shuffles `slice` in place (Fisher-Yates)
+    pub fn shuffle<T>(&mut self, slice: &mut [T]) {
+        for i in (1..slice.len()).rev() {
+            let j = (self.rand() * (i + 1) as f64) as usize;
+            slice.swap(i, j);
+        }
+    }
+
+    /// Returns a normally distributed number (Box-Muller, two draws)
+    pub fn normal(&mut self, mean: f64, std_dev: f64) -> f64 {
+        // `1 - rand()` lies in (0, 1], keeping the log finite
+        let radius = math::sqrt(-2.0 * math::ln(1.0 - self.rand()));
+        let angle = core::f64::consts::TAU * self.rand();
+        mean + std_dev * radius * math::cos(angle)
+    }
+
+    /// Returns a Gamma(`shape`, `scale`) distributed number (Marsaglia-Tsang);
+    /// both parameters must be positive
+    pub fn gamma(&mut self, shape: f64, scale: f64) -> f64 {
+        assert!(shape > 0.0 && scale > 0.0,
+                "invalid gamma parameters ({shape}, {scale})");
+
+        // Boost shapes below 1 into the method's range, see Marsaglia-Tsang
+        if shape < 1.0 {
+            let boost = math::powf(1.0 - self.rand(), 1.0 / shape);
+            return self.gamma(shape + 1.0, scale) * boost;
+        }
+
+        let d = shape - 1.0 / 3.0;
+        let c = 1.0 / math::sqrt(9.0 * d);
+        loop {
+            let x = self.normal(0.0, 1.0);
+            let v = math::powi(1.0 + c * x, 3);
+            if v <= 0.0 { continue; }
+
+            // `1 - rand()` lies in (0, 1], keeping the log finite
+            let u = 1.0 - self.rand();
+            if u < 1.0 - 0.0331 * math::powi(x, 4)
+                    || math::ln(u) < 0.5 * x * x + d * (1.0 - v + math::ln(v)) {
+                return d * v * scale;
+            }
+        }
+    }
+
+    /// Returns a Beta(`a`, `b`) distributed number in [0, 1], from two gamma
+    /// samples
+    pub fn beta(&mut self, a: f64, b: f64) -> f64 {
+        let x = self.gamma(a, 1.0);
+        let y = self.gamma(b, 1.0);
+        x / (x + y)
+    }
+
+    /// Picks an index with probability proportional to its weight. Weights
+    /// must be non-negative with a positive sum
+    pub fn weighted_index(&mut self, weights: &[f64]) -> usize {
+        debug_assert!(weights.iter().all(|&w| w >= 0.0),
+                      "negative weight in {weights:?}");
+        let total: f64 = weights.iter().sum();
+        assert!(total > 0.0, "weights must have a positive sum");
+
+        let target = self.rand() * total;
+        let mut cumulative = 0.0;
+        for (idx, &weight) in weights.iter().enumerate() {
+            cumulative += weight;
+            if target < cumulative { return idx; }
+        }
+
+        // Rounding can leave `target` just past the final sum; fall back to
+        // the last index that can be picked at all
+        weights.iter().rposition(|&w| w > 0.0).unwrap_or_default()
+    }
+
+    /// Like `range` but returns `None` instead of garbage when `max < min`
+    pub fn try_range(&mut self, min: f64, max: f64) -> Option<f64> {
+        (min <= max).then(|| self.range(min, max))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Pearson's chi-squared statistic of `counts` against a uniform spread
+    fn chi_squared(counts: &[usize]) -> f64 {
+        let expected = counts.iter().sum::<usize>() as f64
+            / counts.len() as f64;
+        counts.iter()
+            .map(|&c| (c as f64 - expected).powi(2) / expected)
+            .sum()
+    }
+
+    #[test]
+    fn rand_is_uniform() {
+        let mut rng = Rng::new(42);
+        let mut high = [0usize; 100];
+        let mut low  = [0usize; 128];
+        for _ in 0..1_000_000 {
+            let x = rng.rand();
+            assert!((0.0..1.0).contains(&x));
+            high[(x * 100.0) as usize] += 1;
+
+            // The lowest 7 of the 53 mantissa bits
+            low[((x * (1u64 << 53) as f64) as u64 & 127) as usize] += 1;
+        }
+
+        // The 99.9th percentiles for 99 and 127 degrees of freedom
+        assert!(chi_squared(&high) < 148.2, "{}", chi_squared(&high));
+        assert!(chi_squared(&low)  < 182.0, "{}", chi_squared(&low));
+    }
+
+    #[test]
+    fn rand_is_deterministic() {
+        let mut a = Rng::new(1234);
+        let mut b = Rng::new(1234);
+        let mut c = Rng::new(1235);
+
+        let draws: Vec<f64> = (0..100).map(|_| a.rand()).collect();
+        assert!(draws.iter().all(|&x| x == b.rand()));
+        assert!(draws.iter().any(|&x| x != c.rand()));
+    }
+
+    #[test]
+    fn try_range_rejects_inverted_bounds() {
+        let mut rng = Rng::new(3);
+        assert_eq!(rng.try_range(1.0, 0.0), None);
+        assert_eq!(rng.try_range(5.0, 5.0), Some(5.0));
+
+        let x = rng.try_range(-2.0, 3.0).unwrap();
+        assert!((-2.0..3.0).contains(&x));
+    }
+
+    #[test]
+    fn jump_matches_stepping() {
+        for log2_steps in [0, 1, 5, 12] {
+            let mut stepped = Rng::new(77);
+            let mut jumped = stepped.clone();
+            (0..1usize << log2_steps).for_each(|_| { stepped.rand(); });
+            jumped.jump_pow2(log2_steps);
+            assert_eq!(jumped.0, stepped.0);
+        }
+    }
+
+    #[test]
+    #[ignore = "takes 2^32 draws, run with `cargo test --release -- --ignored`"]
+    fn jump_matches_2_pow_32_draws() {
+        let mut stepped = Rng::new(77);
+        let mut jumped = stepped.clone();
+        (0..1u64 << 32).for_each(|_| { stepped.rand(); });
+        jumped.jump();
+        assert_eq!(jumped.0, stepped.0);
+    }
+
+    #[test]
+    fn fork_is_distinct_and_deterministic() {
+        let mut parent = Rng::new(5);
+        let mut child = parent.fork();
+        let mut again = Rng::new(5).fork();
+
+        let draws: Vec<f64> = (0..16).map(|_| child.rand()).collect();
+        assert!(draws.iter().all(|&x| x == again.rand()));
+        assert!(draws.iter().all(|&x| x != parent.rand()));
+    }
+
+    #[test]
+    fn weighted_index_follows_the_weights() {
+        let mut rng = Rng::new(5);
+        let weights = [1.0, 0.0, 3.0, 6.0];
+        let draws = 100_000;
+        let mut counts = [0usize; 4];
+        for _ in 0..draws {
+            counts[rng.weighted_index(&weights)] += 1;
+        }
+
+        assert_eq!(counts[1], 0);
+        for (&count, &weight) in counts.iter().zip(&weights) {
+            let freq = count as f64 / draws as f64;
+            assert!((freq - weight / 10.0).abs() < 0.01,
+                    "frequencies {counts:?} for weights {weights:?}");
+        }
+    }
+
+    #[test]
+    fn gamma_and_beta_moments() {
+        let mut rng = Rng::new(11);
+        let n = 100_000;
+        let mean_var = |samples: &[f64]| {
+            let mean = samples.iter().sum::<f64>() / samples.len() as f64;
+            let var = samples.iter().map(|s| (s - mean).powi(2)).sum::<f64>()
+                / samples.len() as f64;
+            (mean, var)
+        };
+
+        // Gamma(k, theta) has mean k * theta and variance k * theta^2; a
+        // shape below 1 takes the boosted path
+        for (shape, scale) in [(3.0, 1.0), (0.5, 2.0)] {
+            let samples: Vec<f64> = (0..n)
+                .map(|_| rng.gamma(shape, scale))
+                .collect();
+            assert!(samples.iter().all(|&s| s >= 0.0));
+
+            let (mean, var) = mean_var(&samples);
+            assert!((mean - shape * scale).abs() < 0.03, "mean {mean}");
+            assert!((var / (shape * scale * scale) - 1.0).abs() < 0.05,
+                    "variance {var}");
+        }
+
+        // Beta(2, 2) is symmetric around 0.5 with variance 1/20
+        let samples: Vec<f64> = (0..n).map(|_| rng.beta(2.0, 2.0)).collect();
+        assert!(samples.iter().all(|s| (0.0..=1.0).contains(s)));
+        let (mean, var) = mean_var(&samples);
+        assert!((mean - 0.5).abs() < 0.005, "mean {mean}");
+        assert!((var - 0.05).abs() < 0.002, "variance {var}");
+    }
 }
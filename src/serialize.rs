@@ -0,0 +1,224 @@
+use alloc::vec::Vec;
+use crate::{Context, CtxIdx, Value, OpType};
+
+/// Errors produced by `Context::deserialize`
+#[derive(Debug, Clone, PartialEq)]
+pub enum DeserializeError {
+    /// The blob ended in the middle of the header or a node
+    UnexpectedEof,
+
+    /// Node `idx` has an op tag that doesn't name any `OpType`
+    UnknownOp { idx: CtxIdx, tag: u8 },
+
+    /// Node `idx` refers to an operand that doesn't precede it
+    InvalidOperand { idx: CtxIdx, operand: CtxIdx },
+}
+
+fn op_tag(op: &OpType) -> u8 {
+    match op {
+        OpType::Add          => 1,
+        OpType::Sub          => 2,
+        OpType::Mul          => 3,
+        OpType::Div          => 4,
+        OpType::Tanh         => 5,
+        OpType::Pow          => 6,
+        OpType::Exp          => 7,
+        OpType::Square       => 8,
+        OpType::Softplus     => 9,
+        OpType::Identity     => 10,
+        OpType::Sqrt         => 11,
+        OpType::Ln           => 12,
+        OpType::LeakyTanh(_) => 13,
+        OpType::ScaleGrad(_) => 14,
+        OpType::Swish        => 15,
+    }
+}
+
+fn op_from_tag(tag: u8) -> Option<OpType> {
+    Some(match tag {
+        1  => OpType::Add,
+        2  => OpType::Sub,
+        3  => OpType::Mul,
+        4  => OpType::Div,
+        5  => OpType::Tanh,
+        6  => OpType::Pow,
+        7  => OpType::Exp,
+        8  => OpType::Square,
+        9  => OpType::Softplus,
+        10 => OpType::Identity,
+        11 => OpType::Sqrt,
+        12 => OpType::Ln,
+        // Parameters follow the operands, see `op_param`
+        13 => OpType::LeakyTanh(0.0),
+        14 => OpType::ScaleGrad(0.0),
+        15 => OpType::Swish,
+        _  => return None,
+    })
+}
+
+/// The `f64` an op carries, if any, stored after its operands
+fn op_param(op: &mut OpType) -> Option<&mut f64> {
+    match op {
+        OpType::LeakyTanh(param) | OpType::ScaleGrad(param) => Some(param),
+        _ => None,
+    }
+}
+
+/// Little-endian cursor over a serialized blob
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], DeserializeError> {
+        let (head, rest) = self.0.split_first_chunk::<N>()
+            .ok_or(DeserializeError::UnexpectedEof)?;
+        self.0 = rest;
+        Ok(*head)
+    }
+
+    fn u8(&mut self) -> Result<u8, DeserializeError> {
+        Ok(self.take::<1>()?[0])
+    }
+
+    fn u64(&mut self) -> Result<u64, DeserializeError> {
+        Ok(u64::from_le_bytes(self.take()?))
+    }
+
+    fn f64(&mut self) -> Result<f64, DeserializeError> {
+        Ok(f64::from_le_bytes(self.take()?))
+    }
+}
+
+impl Context {
+    /// Encodes the whole graph (every node's `data`, `grad`, `op` and
+    /// `requires_grad`, plus the context's `eps`) into a compact blob.
+    ///
+    /// Layout, all little-endian: `eps: f64`, `len: u64`, then per node
+    /// `data: f64`, `grad: f64`, `requires_grad: u8`, `op tag: u8` (`0` for
+    /// leaves) and, for op nodes, both operands as `u64` followed by the op's
+    /// `f64` parameter if it has one (e.g. the floor of `LeakyTanh`)
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&self.eps().to_le_bytes());
+        bytes.extend_from_slice(&(self.len() as u64).to_le_bytes());
+
+        for idx in 0..self.len() {
+            let val = self.get(idx);
+            bytes.extend_from_slice(&val.data.to_le_bytes());
+            bytes.extend_from_slice(&val.grad.to_le_bytes());
+            bytes.push(val.requires_grad as u8);
+
+            match val.op {
+                None => bytes.push(0),
+                Some((mut optype, operands)) => {
+                    bytes.push(op_tag(&optype));
+                    for operand in operands.map(|o| o as u64) {
+                        bytes.extend_from_slice(&operand.to_le_bytes());
+                    }
+                    if let Some(param) = op_param(&mut optype) {
+                        bytes.extend_from_slice(&param.to_le_bytes());
+                    }
+                },
+            }
+        }
+        bytes
+    }
+
+    /// Rebuilds a context from a blob produced by `serialize`
+    pub fn deserialize(bytes: &[u8]) -> Result<Context, DeserializeError> {
+        let mut reader = Reader(bytes);
+        let mut ctx = Context::new();
+        ctx.set_eps(reader.f64()?);
+
+        let len = reader.u64()?;
+        for idx in 0..len as usize {
+            let data = reader.f64()?;
+            let grad = reader.f64()?;
+            let requires_grad = reader.u8()? != 0;
+
+            let op = match reader.u8()? {
+                0 => None,
+                tag => {
+                    let mut optype = op_from_tag(tag)
+                        .ok_or(DeserializeError::UnknownOp { idx, tag })?;
+                    let operands = [reader.u64()? as CtxIdx,
+                                    reader.u64()? as CtxIdx];
+
+                    let invalid = operands.iter().find(|&&o| o >= idx);
+                    if let Some(&operand) = invalid {
+                        return Err(DeserializeError::InvalidOperand {
+                            idx, operand
+                        });
+                    }
+                    if let Some(param) = op_param(&mut optype) {
+                        *param = reader.f64()?;
+                    }
+                    Some((optype, operands))
+                },
+            };
+
+            ctx.push_val(Value { data, grad, op, requires_grad });
+        }
+
+        Ok(ctx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip_preserves_graph_and_gradients() {
+        let mut ctx = Context::new();
+        ctx.set_eps(1e-6);
+        let x = ctx.push(0.7);
+        let w = ctx.push_param(-1.3);
+        let prod  = ctx.mul(x, w);
+        let leaky = ctx.leaky_tanh(prod, 0.05);
+        let rev   = ctx.grad_reverse(leaky, 0.5);
+        let sw    = ctx.swish(rev);
+        let two   = ctx.push(2.0);
+        let pw    = ctx.pow(x, two);
+        let out   = ctx.safe_div_default(sw, pw);
+        ctx.backward(out);
+
+        let mut copy = Context::deserialize(&ctx.serialize()).unwrap();
+        assert_eq!(copy.len(), ctx.len());
+        assert_eq!(copy.eps(), ctx.eps());
+        for idx in 0..ctx.len() {
+            let (a, b) = (ctx.get(idx), copy.get(idx));
+            assert_eq!((a.data, a.grad, a.op, a.requires_grad),
+                       (b.data, b.grad, b.op, b.requires_grad));
+        }
+
+        ctx.backward(out);
+        copy.backward(out);
+        for idx in 0..ctx.len() {
+            assert_eq!(ctx.grad(idx), copy.grad(idx));
+        }
+    }
+
+    #[test]
+    fn deserialize_rejects_corrupted_blobs() {
+        let mut ctx = Context::new();
+        let a = ctx.push(1.0);
+        let b = ctx.push(2.0);
+        ctx.add(a, b);
+        let bytes = ctx.serialize();
+
+        // The header takes 16 bytes and each leaf 18, so node 2's op tag is
+        // at byte 69, followed by its operands
+        let mut bad_tag = bytes.clone();
+        bad_tag[69] = 200;
+        assert_eq!(Context::deserialize(&bad_tag).unwrap_err(),
+                   DeserializeError::UnknownOp { idx: 2, tag: 200 });
+
+        let mut bad_operand = bytes.clone();
+        bad_operand[70] = 5;
+        assert_eq!(Context::deserialize(&bad_operand).unwrap_err(),
+                   DeserializeError::InvalidOperand { idx: 2, operand: 5 });
+
+        assert_eq!(Context::deserialize(&bytes[..bytes.len() - 1]).unwrap_err(),
+                   DeserializeError::UnexpectedEof);
+    }
+}
@@ -0,0 +1,178 @@
+use alloc::{vec, vec::Vec};
+use core::num::NonZero;
+use crate::{Context, CtxIdx, MultiLayerPerceptron, Rng};
+
+/// A dataset as `(inputs, targets)`, one `Vec` per sample
+pub type Samples<'a> = (&'a [Vec<f64>], &'a [Vec<f64>]);
+
+/// Per-epoch learning curves recorded by `Trainer::fit`
+#[derive(Debug, Clone, Default)]
+pub struct History {
+    pub train_loss: Vec<f64>,
+    pub val_loss: Vec<f64>,
+    pub lr: Vec<f64>,
+}
+
+/// (Mini-)batch gradient descent on the sum of squared errors
+#[derive(Debug, Clone)]
+pub struct Trainer {
+    pub learning_rate: f64,
+    pub epochs: usize,
+
+    /// Samples per update step, `None` for full-batch training
+    pub batch_size: Option<NonZero<usize>>,
+
+    /// Reshuffle the sample order every epoch; disable for debugging
+    pub shuffle: bool,
+
+    rng: Rng,
+}
+
+impl Trainer {
+    /// A full-batch trainer with shuffling enabled, drawing sample orders
+    /// from `rng`
+    pub fn new(learning_rate: f64, epochs: usize, rng: Rng) -> Self {
+        Self {
+            learning_rate,
+            epochs,
+            batch_size: None,
+            shuffle: true,
+            rng,
+        }
+    }
+
+    /// The order the next epoch visits `n` samples in; `0..n` unless
+    /// shuffling is enabled
+    pub fn epoch_order(&mut self, n: usize) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..n).collect();
+        if self.shuffle {
+            self.rng.shuffle(&mut order);
+        }
+        order
+    }
+
+    /// Builds the summed squared error of `mlp` over the `batch` samples
+    fn loss(ctx: &mut Context, mlp: &MultiLayerPerceptron,
+            (inputs, targets): Samples, batch: &[usize]) -> CtxIdx {
+        let mut loss_terms = vec![];
+        for &sample in batch {
+            let x = ctx.push_many(&inputs[sample]);
+            let preds = mlp.forward(ctx, &x);
+            let target = targets[sample].iter();
+
+            for (&pred_idx, &target) in preds.iter().zip(target) {
+                let target_idx = ctx.push(target);
+                let diff_idx   = ctx.sub(pred_idx, target_idx);
+                loss_terms.push(ctx.mul(diff_idx, diff_idx));
+            }
+        }
+        ctx.sum(&loss_terms)
+    }
+
+    /// Trains `mlp` for `self.epochs` epochs. The recorded training loss is
+    /// the sum of the batch losses seen during the epoch; the validation
+    /// loss (if a validation set is given) is measured after the epoch.
+    ///
+    /// Every node pushed during training is truncated away again, so the
+    /// context is left with the same length it had before the call
+    pub fn fit(&mut self, ctx: &mut Context, mlp: &MultiLayerPerceptron,
+               train: Samples, validation: Option<Samples>) -> History {
+        let mark = ctx.len();
+        let mut history = History::default();
+        let n_samples = train.0.len();
+        let batch_size = self.batch_size.map_or(n_samples, NonZero::get);
+
+        for _ in 0..self.epochs {
+            let order = self.epoch_order(n_samples);
+            let mut epoch_loss = 0.0;
+
+            for batch in order.chunks(batch_size.max(1)) {
+                let loss_idx = Self::loss(ctx, mlp, train, batch);
+                epoch_loss += ctx.value(loss_idx);
+                ctx.backward(loss_idx);
+
+                // p.data += -learning_rate * p.grad
+                for param in mlp.parameters() {
+                    if !ctx.get(param).requires_grad { continue; }
+                    let grad   = ctx.grad(param);
+                    let update = ctx.value(param) - self.learning_rate * grad;
+                    ctx.get_mut(param).data = update;
+                }
+                ctx.truncate(mark);
+            }
+            history.train_loss.push(epoch_loss);
+            history.lr.push(self.learning_rate);
+
+            if let Some(validation) = validation {
+                let all: Vec<usize> = (0..validation.0.len()).collect();
+                let loss_idx = Self::loss(ctx, mlp, validation, &all);
+                history.val_loss.push(ctx.value(loss_idx));
+                ctx.truncate(mark);
+            }
+        }
+
+        history
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dataset() -> (Vec<Vec<f64>>, Vec<Vec<f64>>) {
+        let inputs = vec![
+            vec![2.0,  3.0, -1.0],
+            vec![3.0, -1.0,  0.5],
+            vec![0.5,  1.0,  1.0],
+            vec![1.0,  1.0, -1.0],
+        ];
+        let targets = vec![vec![1.0], vec![-1.0], vec![-1.0], vec![1.0]];
+        (inputs, targets)
+    }
+
+    #[test]
+    fn fit_records_one_entry_per_epoch() {
+        let (inputs, targets) = dataset();
+        let (mut ctx, rng, mlp) = MultiLayerPerceptron::quick(&[3, 4, 1], 3)
+            .unwrap();
+        let mut trainer = Trainer::new(0.05, 7, rng);
+
+        let train = (inputs.as_slice(), targets.as_slice());
+        let history = trainer.fit(&mut ctx, &mlp, train, Some(train));
+        for curve in [&history.train_loss, &history.val_loss, &history.lr] {
+            assert_eq!(curve.len(), 7);
+            assert!(curve.iter().all(|v| v.is_finite()));
+        }
+    }
+
+    #[test]
+    fn shuffling_is_per_epoch_and_reproducible() {
+        let orders = |shuffle: bool| {
+            let mut trainer = Trainer::new(0.1, 1, Rng::new(11));
+            trainer.shuffle = shuffle;
+            (0..3).map(|_| trainer.epoch_order(8)).collect::<Vec<_>>()
+        };
+
+        let fixed = orders(false);
+        assert!(fixed.iter().all(|order| *order == (0..8).collect::<Vec<_>>()));
+        assert_eq!(fixed, orders(false));
+
+        let shuffled = orders(true);
+        assert_ne!(shuffled[0], shuffled[1]);
+        assert_ne!(shuffled[1], shuffled[2]);
+        assert_eq!(shuffled, orders(true));
+    }
+
+    #[test]
+    fn fit_is_reproducible_from_the_seed() {
+        let (inputs, targets) = dataset();
+        let run = || {
+            let (mut ctx, _, mlp) = MultiLayerPerceptron::quick(&[3, 4, 1], 3)
+                .unwrap();
+            let mut trainer = Trainer::new(0.05, 5, Rng::new(21));
+            trainer.batch_size = NonZero::new(2);
+            trainer.fit(&mut ctx, &mlp, (&inputs, &targets), None).train_loss
+        };
+        assert_eq!(run(), run());
+    }
+}
@@ -1,7 +1,13 @@
+#[cfg(feature = "std")]
+use std::time::{Duration, Instant};
+
+use alloc::{format, string::String, vec, vec::Vec};
+use crate::math;
+
 pub type Operation = (OpType, [CtxIdx; 2]);
 pub type CtxIdx = usize;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum OpType {
     Add,
     Sub,
@@ -10,6 +16,32 @@ pub enum OpType {
     Tanh,
     Pow,
     Exp,
+    Square,
+    Softplus,
+    Identity,
+    Sqrt,
+    Ln,
+    /// `tanh` whose backward derivative never drops below the given floor
+    LeakyTanh(f64),
+    /// Identity whose backward multiplies the gradient by the given factor
+    ScaleGrad(f64),
+    Swish,
+}
+
+/// The op node at `idx` refers to an `operand` that doesn't precede it, see
+/// `Context::validate`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationError {
+    pub idx: CtxIdx,
+    pub operand: CtxIdx,
+}
+
+/// The op node at `idx` produced a non-finite gradient, see
+/// `Context::try_backward`
+#[derive(Debug, Clone, PartialEq)]
+pub struct BackwardError {
+    pub idx: CtxIdx,
+    pub op: OpType,
 }
 
 #[derive(Debug, Clone)]
@@ -17,6 +49,9 @@ pub struct Value {
     pub data: f64,
     pub grad: f64,
     pub op: Option<Operation>,
+
+    /// Marks trainable parameters, see `Context::push_param`
+    pub requires_grad: bool,
 }
 
 impl Value {
@@ -25,6 +60,7 @@ impl Value {
             data,
             grad: 0.0,
             op: Some(op),
+            requires_grad: false,
         }
     }
 
@@ -33,28 +69,241 @@ impl Value {
             data,
             grad: 0.0,
             op: None,
+            requires_grad: false,
         }
     }
+
+    pub fn new_param(data: f64) -> Self {
+        Self {
+            requires_grad: true,
+            ..Self::new_const(data)
+        }
+    }
+}
+
+/// `1 / (1 + exp(-x))`, evaluated so that `exp` never overflows
+fn sigmoid(x: f64) -> f64 {
+    if x >= 0.0 {
+        1.0 / (1.0 + math::exp(-x))
+    } else {
+        let e = math::exp(x);
+        e / (1.0 + e)
+    }
+}
+
+/// `ln(1 + exp(x))`, rewritten as `max(x, 0) + ln(1 + exp(-|x|))` so `exp`
+/// never overflows
+fn softplus(x: f64) -> f64 {
+    x.max(0.0) + math::ln_1p(math::exp(-x.abs()))
+}
+
+/// The forward value of an `op` node over operand data `a` and `b` (unary
+/// ops ignore `b`). Every path that computes node data goes through this,
+/// so node construction and recomputation can't disagree
+pub fn eval_op(op: &OpType, a: f64, b: f64) -> f64 {
+    match op {
+        OpType::Add          => a + b,
+        OpType::Sub          => a - b,
+        OpType::Mul          => a * b,
+        OpType::Div          => a / b,
+        OpType::Pow          => math::powf(a, b),
+        OpType::Exp          => math::exp(a),
+        OpType::Square       => a * a,
+        OpType::Softplus     => softplus(a),
+        OpType::Identity     => a,
+        OpType::ScaleGrad(_) => a,
+        OpType::Sqrt         => math::sqrt(a),
+        OpType::Ln           => math::ln(a),
+        OpType::Swish        => a * sigmoid(a),
+        OpType::Tanh | OpType::LeakyTanh(_) => {
+            let x = math::exp(2.0 * a) - 1.0;
+            let y = math::exp(2.0 * a) + 1.0;
+            x / y
+        },
+    }
+}
+
+/// The symbol `Context::expr` writes between the operands of a binary op,
+/// or `None` for unary ops
+fn infix_symbol(op: OpType) -> Option<&'static str> {
+    match op {
+        OpType::Add => Some("+"),
+        OpType::Sub => Some("-"),
+        OpType::Mul => Some("*"),
+        OpType::Div => Some("/"),
+        OpType::Pow => Some("^"),
+        _           => None,
+    }
 }
 
-#[derive(Debug)]
+/// The arena every node of a computation graph lives in.
+///
+/// Cloning a context is a full, independent deep copy: every `CtxIdx` valid
+/// in the original refers to the same node in the clone, and changes to one
+/// never affect the other.
+///
+/// Every node's `data` and `grad` stay in the arena until the node is dropped
+/// by `truncate` or `compact`, and `backward` reads the data of every
+/// intermediate of the forward pass. There is no gradient checkpointing;
+/// memory over many samples is bounded by truncating each forward pass's
+/// scratch nodes once its backward is done
+#[derive(Debug, Clone)]
 pub struct Context {
     values: Vec<Value>,
+
+    /// Nodes flagged by `mark_dirty` that are waiting for `recompute_dirty`
+    dirty: Vec<bool>,
+
+    /// Default stability constant of guarded ops, see `safe_div_default`
+    eps: f64,
+
+    /// Whether `backward` keeps the gradients of intermediate (op) nodes
+    retain_grad: bool,
+
+    /// Pushing beyond this many nodes panics, see `set_max_nodes`
+    max_nodes: usize,
+
+    /// Cumulative `(apply_op, backward)` time, `None` while not enabled
+    #[cfg(feature = "std")]
+    timing: Option<(Duration, Duration)>,
+}
+
+impl Default for Context {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl core::ops::Index<CtxIdx> for Context {
+    type Output = Value;
+
+    fn index(&self, idx: CtxIdx) -> &Value {
+        self.get(idx)
+    }
+}
+
+impl core::ops::IndexMut<CtxIdx> for Context {
+    fn index_mut(&mut self, idx: CtxIdx) -> &mut Value {
+        self.get_mut(idx)
+    }
 }
 
 impl Context {
     pub fn new() -> Self {
         Self {
             values: Vec::new(),
+            dirty:  Vec::new(),
+            eps:    1e-12,
+            retain_grad: true,
+            max_nodes: usize::MAX,
+            #[cfg(feature = "std")]
+            timing: None,
         }
     }
 
+    pub fn with_capacity(n: usize) -> Self {
+        Self {
+            values: Vec::with_capacity(n),
+            ..Self::new()
+        }
+    }
+
+    /// Starts (and resets) or stops recording the time spent in graph
+    /// construction and `backward`, see `timings`
+    #[cfg(feature = "std")]
+    pub fn enable_timing(&mut self, enable: bool) {
+        self.timing = enable.then_some((Duration::ZERO, Duration::ZERO));
+    }
+
+    /// Cumulative `(op construction, backward)` time since timing was
+    /// enabled; zero while it isn't
+    #[cfg(feature = "std")]
+    pub fn timings(&self) -> (Duration, Duration) {
+        self.timing.unwrap_or((Duration::ZERO, Duration::ZERO))
+    }
+
+    pub fn reserve(&mut self, additional: usize) {
+        self.values.reserve(additional);
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.values.capacity()
+    }
+
+    pub fn eps(&self) -> f64 {
+        self.eps
+    }
+
+    pub fn set_eps(&mut self, eps: f64) {
+        self.eps = eps;
+    }
+
+    /// With `retain` disabled, `backward` zeroes each intermediate (op) node's
+    /// gradient as soon as it has been propagated to its operands, so only
+    /// leaf gradients (inputs/parameters) are left afterwards
+    pub fn set_retain_grad(&mut self, retain: bool) {
+        self.retain_grad = retain;
+    }
+
+    /// Makes any push that would grow the graph past `limit` nodes panic,
+    /// catching runaway graph growth before it exhausts memory. Unlimited
+    /// (`usize::MAX`) by default
+    pub fn set_max_nodes(&mut self, limit: usize) {
+        self.max_nodes = limit;
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Drops every node from `len` onwards, e.g. to discard the scratch
+    /// nodes of a forward pass while keeping the parameters pushed before it
+    pub fn truncate(&mut self, len: usize) {
+        self.values.truncate(len);
+        self.dirty.truncate(len);
+    }
+
+    /// Reads the data of `outputs`, then truncates to `checkpoint` (usually
+    /// `len()` taken before a forward pass). Outputs may be scratch nodes
+    /// above the mark since they're read first, but any such index is
+    /// invalid once this returns
+    pub fn eval_and_truncate(&mut self, checkpoint: CtxIdx,
+                             outputs: &[CtxIdx]) -> Vec<f64> {
+        let vals = self.values(outputs).collect();
+        self.truncate(checkpoint);
+        vals
+    }
+
     pub fn push(&mut self, val: f64) -> CtxIdx {
         self.push_val(Value::new_const(val))
     }
 
+    /// Pushes every value in order, returning their (contiguous) indices
+    pub fn push_many(&mut self, vals: &[f64]) -> Vec<CtxIdx> {
+        self.push_iter(vals.iter().copied())
+    }
+
+    /// Like `push_many`, but takes the values from any iterator
+    pub fn push_iter<I>(&mut self, vals: I) -> Vec<CtxIdx>
+    where
+        I: IntoIterator<Item = f64>,
+    {
+        vals.into_iter().map(|v| self.push(v)).collect()
+    }
+
+    /// Pushes a trainable parameter, as opposed to a constant/input
+    pub fn push_param(&mut self, val: f64) -> CtxIdx {
+        self.push_val(Value::new_param(val))
+    }
+
     pub fn push_val(&mut self, val: Value) -> CtxIdx {
         let idx = self.values.len();
+        assert!(idx < self.max_nodes,
+                "context exceeded its limit of {} nodes", self.max_nodes);
         self.values.push(val);
         idx
     }
@@ -71,139 +320,1901 @@ impl Context {
         &mut self.values[idx]
     }
 
+    /// Whether `idx` is a constant/input/parameter rather than an op result
+    pub fn is_leaf(&self, idx: CtxIdx) -> bool {
+        self.values[idx].op.is_none()
+    }
+
+    pub fn op_of(&self, idx: CtxIdx) -> Option<&OpType> {
+        self.values[idx].op.as_ref().map(|(optype, _)| optype)
+    }
+
     pub fn values<'a>(&'a self, indices: &'a [CtxIdx])
             -> impl Iterator<Item = f64> + 'a {
         indices.iter().map(move |&i| self.value(i))
     }
 
+    /// Iterates over every node pushed with `push_param`
+    pub fn parameters(&self) -> impl Iterator<Item = CtxIdx> + '_ {
+        self.values.iter()
+            .enumerate()
+            .filter(|(_, v)| v.requires_grad)
+            .map(|(i, _)| i)
+    }
+
     pub fn grad(&self, idx: CtxIdx) -> f64 {
         self.values[idx].grad
     }
 
+    /// Euclidean norm of the gradients of `indices`
+    pub fn grad_norm(&self, indices: &[CtxIdx]) -> f64 {
+        math::sqrt(indices.iter().map(|&i| math::powi(self.grad(i), 2)).sum())
+    }
+
+    /// Euclidean norm of the values of `indices`
+    pub fn value_norm(&self, indices: &[CtxIdx]) -> f64 {
+        math::sqrt(self.values(indices).map(|v| math::powi(v, 2)).sum())
+    }
+
+    /// `(min, max, mean)` of the gradients of `params`, all NaN if empty
+    pub fn grad_stats(&self, params: &[CtxIdx]) -> (f64, f64, f64) {
+        if params.is_empty() {
+            return (f64::NAN, f64::NAN, f64::NAN);
+        }
+
+        let grads = params.iter().map(|&p| self.grad(p));
+        let min = grads.clone().fold(f64::INFINITY, f64::min);
+        let max = grads.clone().fold(f64::NEG_INFINITY, f64::max);
+        let mean = grads.sum::<f64>() / params.len() as f64;
+        (min, max, mean)
+    }
+
+    /// Copies out the data of `params`, see `restore_values`
+    pub fn snapshot_values(&self, params: &[CtxIdx]) -> Vec<f64> {
+        self.values(params).collect()
+    }
+
+    /// Writes a `snapshot_values` result back into the same `params`, e.g.
+    /// to roll back a rejected optimizer step
+    pub fn restore_values(&mut self, params: &[CtxIdx], snapshot: &[f64]) {
+        assert_eq!(params.len(), snapshot.len(),
+                   "params and snapshot differ in length");
+
+        for (&p, &data) in params.iter().zip(snapshot.iter()) {
+            self.values[p].data = data;
+        }
+    }
+
+    /// Pairs each of `indices` with its gradient, in order
+    pub fn grad_map(&self, indices: &[CtxIdx]) -> Vec<(CtxIdx, f64)> {
+        indices.iter().map(|&i| (i, self.grad(i))).collect()
+    }
+
+    /// Panics with the offending node if any `data` or `grad` is NaN/inf
+    pub fn assert_finite(&self) {
+        for (idx, val) in self.values.iter().enumerate() {
+            assert!(val.data.is_finite() && val.grad.is_finite(),
+                    "non-finite node {idx} (op: {:?}): data = {}, grad = {}",
+                    val.op, val.data, val.grad);
+        }
+    }
+
+    /// Checks that every op node's operands precede it, the invariant all
+    /// traversals rely on. Catches graphs corrupted through `get_mut` or
+    /// the editing APIs, reporting the first offending node
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        for (idx, val) in self.values.iter().enumerate() {
+            let Some((_, operands)) = val.op else { continue; };
+            if let Some(&operand) = operands.iter().find(|&&o| o >= idx) {
+                return Err(ValidationError { idx, operand });
+            }
+        }
+        Ok(())
+    }
+
     pub fn clear_grad(&mut self, idx: CtxIdx) {
         self.values[idx].grad = 0.0;
     }
 
-    fn apply_op<F>(&mut self, idx1: CtxIdx, idx2: CtxIdx,
-                   op_type: OpType, op: F) -> CtxIdx
+    /// Zeroes the gradient of every one of `indices`
+    pub fn clear_grads(&mut self, indices: &[CtxIdx]) {
+        self.clear_grads_iter(indices.iter().copied());
+    }
+
+    /// Like `clear_grads`, but takes the indices from any iterator, e.g.
+    /// `mlp.parameters()`
+    pub fn clear_grads_iter<I>(&mut self, indices: I)
     where
-        F: Fn(f64, f64) -> f64,
+        I: IntoIterator<Item = CtxIdx>,
     {
-        let result = op(self.values[idx1].data, self.values[idx2].data);
-        self.push_val(Value::new(result, (op_type, [idx1, idx2])))
+        indices.into_iter().for_each(|idx| self.clear_grad(idx));
+    }
+
+    /// Flags `idx` and every node that (transitively) consumes it for
+    /// recomputation. Call this after changing the `data` of a node in place
+    pub fn mark_dirty(&mut self, idx: CtxIdx) {
+        self.dirty.resize(self.values.len(), false);
+        self.dirty[idx] = true;
+        self.propagate_dirty(idx);
+    }
+
+    /// Rewrites node `idx` to compute `op` over `operands` and recomputes its
+    /// data. Its consumers are marked dirty; `recompute_dirty` updates them
+    pub fn set_op(&mut self, idx: CtxIdx, op: OpType, operands: [CtxIdx; 2]) {
+        // Keeps ascending index order a topological order
+        assert!(operands.iter().all(|&o| o < idx),
+                "operands of node {idx} must precede it");
+
+        let [a, b] = operands;
+        self.values[idx].data = eval_op(&op,
+                                        self.values[a].data,
+                                        self.values[b].data);
+        self.values[idx].op = Some((op, operands));
+        self.mark_dirty(idx);
+    }
+
+    fn propagate_dirty(&mut self, from: CtxIdx) {
+        // Operands always precede their results, so a single forward sweep
+        // is enough to propagate the flag to every descendant
+        for i in from + 1..self.values.len() {
+            if let Some((_, [a, b])) = self.values[i].op {
+                if self.dirty[a] || self.dirty[b] {
+                    self.dirty[i] = true;
+                }
+            }
+        }
+    }
+
+    /// Re-evaluates only the nodes flagged by `mark_dirty`, in topological
+    /// order, and clears the flags
+    pub fn recompute_dirty(&mut self) {
+        for idx in 0..self.dirty.len() {
+            if !self.dirty[idx] { continue; }
+
+            if let Some((ref optype, [a, b])) = self.values[idx].op {
+                let data = eval_op(optype,
+                                   self.values[a].data, self.values[b].data);
+                self.values[idx].data = data;
+            }
+        }
+        self.dirty.clear();
+    }
+
+    /// Pushes `n` input slots to build a reusable graph against, see `bind`
+    pub fn placeholders(&mut self, n: usize) -> Vec<CtxIdx> {
+        (0..n).map(|_| self.push(0.0)).collect()
+    }
+
+    /// Writes new input data into `placeholders` and marks everything that
+    /// depends on them dirty, so that a following `recompute_dirty`
+    /// evaluates the existing graph on the new inputs without rebuilding it
+    pub fn bind(&mut self, placeholders: &[CtxIdx], values: &[f64]) {
+        assert_eq!(placeholders.len(), values.len(),
+                   "placeholders and values differ in length");
+
+        self.dirty.resize(self.values.len(), false);
+        for (&p, &v) in placeholders.iter().zip(values.iter()) {
+            self.values[p].data = v;
+            self.dirty[p] = true;
+        }
+
+        if let Some(&first) = placeholders.iter().min() {
+            self.propagate_dirty(first);
+        }
+    }
+
+    /// Flags every node in `roots` and all of their ancestors
+    fn reachable(&self, roots: &[CtxIdx]) -> Vec<bool> {
+        let mut live = vec![false; self.values.len()];
+        roots.iter().for_each(|&r| live[r] = true);
+
+        for idx in (0..live.len()).rev() {
+            if !live[idx] { continue; }
+            if let Some((_, [a, b])) = self.values[idx].op {
+                live[a] = true;
+                live[b] = true;
+            }
+        }
+        live
+    }
+
+    /// The ancestors of `output_idx` (inclusive) in topological order.
+    /// Operands always precede their results in the context, so this is
+    /// simply ascending index order
+    pub fn topo_order(&self, output_idx: CtxIdx) -> Vec<CtxIdx> {
+        self.reachable(&[output_idx]).iter()
+            .enumerate()
+            .filter(|(_, &live)| live)
+            .map(|(idx, _)| idx)
+            .collect()
+    }
+
+    /// Length of the longest path from any leaf to `output_idx`, in ops
+    /// (a leaf has depth 0)
+    pub fn graph_depth(&self, output_idx: CtxIdx) -> usize {
+        let mut depth = vec![0; output_idx + 1];
+        for idx in 0..=output_idx {
+            if let Some((_, [a, b])) = self.values[idx].op {
+                depth[idx] = 1 + depth[a].max(depth[b]);
+            }
+        }
+        depth[output_idx]
+    }
+
+    /// Renders node `idx` as an expression, e.g. `tanh(((v0 * v1) + v2))`,
+    /// naming leaves `v<index>`. An op node used more than once is written
+    /// out the first time as `#<index>=expr` and as just `#<index>` after
+    /// that, so the output stays linear in the graph's size. Anything nested
+    /// deeper than 32 ops is cut off as `...`
+    pub fn expr(&self, idx: CtxIdx) -> String {
+        // How many times each node is written as an operand
+        let live = self.reachable(&[idx]);
+        let mut uses = vec![0usize; idx + 1];
+        for node in (0..=idx).filter(|&node| live[node]) {
+            if let Some((optype, [a, b])) = self.values[node].op {
+                uses[a] += 1;
+                if infix_symbol(optype).is_some() { uses[b] += 1; }
+            }
+        }
+
+        let mut out = String::new();
+        let mut written = vec![false; idx + 1];
+        self.write_expr(&mut out, idx, 32, &uses, &mut written);
+        out
+    }
+
+    fn write_expr(&self, out: &mut String, idx: CtxIdx, depth: usize,
+                  uses: &[usize], written: &mut [bool]) {
+        let Some((optype, [a, b])) = self.values[idx].op else {
+            out.push_str(&format!("v{idx}"));
+            return;
+        };
+        if written[idx] {
+            out.push_str(&format!("#{idx}"));
+            return;
+        }
+        if depth == 0 {
+            out.push_str("...");
+            return;
+        }
+
+        if uses[idx] > 1 {
+            out.push_str(&format!("#{idx}="));
+            written[idx] = true;
+        }
+
+        if let Some(symbol) = infix_symbol(optype) {
+            out.push('(');
+            self.write_expr(out, a, depth - 1, uses, written);
+            out.push_str(&format!(" {symbol} "));
+            self.write_expr(out, b, depth - 1, uses, written);
+            out.push(')');
+            return;
+        }
+
+        let (name, param) = match optype {
+            OpType::Tanh              => ("tanh", None),
+            OpType::Exp               => ("exp", None),
+            OpType::Square            => ("square", None),
+            OpType::Softplus          => ("softplus", None),
+            OpType::Identity          => ("identity", None),
+            OpType::Sqrt              => ("sqrt", None),
+            OpType::Ln                => ("ln", None),
+            OpType::Swish             => ("swish", None),
+            OpType::LeakyTanh(floor)  => ("leaky_tanh", Some(floor)),
+            OpType::ScaleGrad(factor) => ("scale_grad", Some(factor)),
+            _ => unreachable!("binary ops are rendered infix"),
+        };
+        out.push_str(name);
+        out.push('(');
+        self.write_expr(out, a, depth - 1, uses, written);
+        if let Some(param) = param {
+            out.push_str(&format!(", {param}"));
+        }
+        out.push(')');
+    }
+
+    /// Yields `(idx, grad)` for every ancestor of `output_idx` (inclusive),
+    /// operands before their results
+    pub fn iter_grads_topo(&self, output_idx: CtxIdx)
+            -> impl Iterator<Item = (CtxIdx, f64)> + '_ {
+        self.topo_order(output_idx).into_iter().map(|i| (i, self.grad(i)))
+    }
+
+    /// Rewrites every `div` whose denominator is shared with other `div`s
+    /// into a `mul` by a single reciprocal node `1 / denominator`, inserted
+    /// right before its first use. Gradients are preserved by construction
+    /// but zeroed, like in `compact`. Since the inserted nodes shift every
+    /// later index, the new index of each old node is returned
+    pub fn fuse_common_reciprocals(&mut self) -> Vec<CtxIdx> {
+        // How many `div`s divide by each node
+        let mut uses = vec![0usize; self.values.len()];
+        for val in self.values.iter() {
+            if let Some((OpType::Div, [_, denom])) = val.op {
+                uses[denom] += 1;
+            }
+        }
+
+        let mut remap = vec![0; self.values.len()];
+        let mut reciprocals: Vec<Option<CtxIdx>> = vec![None; uses.len()];
+        let old = core::mem::take(&mut self.values);
+        let mut values = Vec::with_capacity(old.len());
+
+        for (idx, mut val) in old.into_iter().enumerate() {
+            if let Some((ref mut optype, ref mut operands)) = val.op {
+                let denom = operands[1];
+                *operands = operands.map(|op| remap[op]);
+
+                if *optype == OpType::Div && uses[denom] > 1 {
+                    let recip = match reciprocals[denom] {
+                        Some(recip) => recip,
+                        None => {
+                            // Keeps the ones-constant and the reciprocal ahead
+                            // of every consumer
+                            let one = values.len();
+                            values.push(Value::new_const(1.0));
+                            let recip = values.len();
+                            let data = eval_op(&OpType::Div, 1.0,
+                                               values[operands[1]].data);
+                            values.push(Value::new(data, (OpType::Div,
+                                                          [one, operands[1]])));
+                            reciprocals[denom] = Some(recip);
+                            recip
+                        },
+                    };
+
+                    *optype = OpType::Mul;
+                    operands[1] = recip;
+                }
+            }
+            val.grad = 0.0;
+
+            remap[idx] = values.len();
+            values.push(val);
+        }
+
+        self.values = values;
+        self.dirty.clear();
+        remap
+    }
+
+    /// Drops every node that isn't in `keep` or an ancestor of it, and zeroes
+    /// all gradients. Surviving nodes are renumbered (in their original
+    /// order), so the new index of each `keep` node is returned
+    pub fn compact(&mut self, keep: &[CtxIdx]) -> Vec<CtxIdx> {
+        let live = self.reachable(keep);
+        let mut remap = vec![0; self.values.len()];
+        let n_live = live.iter().filter(|&&l| l).count();
+        let mut values = Vec::with_capacity(n_live);
+
+        let old = core::mem::take(&mut self.values);
+        for (idx, mut val) in old.into_iter().enumerate() {
+            if !live[idx] { continue; }
+
+            if let Some((_, ref mut operands)) = val.op {
+                *operands = operands.map(|op| remap[op]);
+            }
+            val.grad = 0.0;
+
+            remap[idx] = values.len();
+            values.push(val);
+        }
+
+        self.values = values;
+        self.dirty.clear();
+        keep.iter().map(|&k| remap[k]).collect()
+    }
+
+    // Unary ops pass their single operand as both `idx1` and `idx2`, so
+    // every operand index of a node refers to a real ancestor
+    fn apply_op(&mut self, idx1: CtxIdx, idx2: CtxIdx,
+                op_type: OpType) -> CtxIdx {
+        #[cfg(feature = "std")]
+        let start = self.timing.map(|_| Instant::now());
+
+        let result = eval_op(&op_type, self.values[idx1].data,
+                             self.values[idx2].data);
+        let idx = self.push_val(Value::new(result, (op_type, [idx1, idx2])));
+
+        #[cfg(feature = "std")]
+        if let (Some(start), Some((forward, _))) = (start, &mut self.timing) {
+            *forward += start.elapsed();
+        }
+        idx
+    }
+
+    /// Pushes an `op` node over `a` and `b` (pass the operand twice for unary
+    /// ops), checking that both operands exist. The error's `idx` is the
+    /// index the node would have gotten
+    pub fn op_node(&mut self, op: OpType, a: CtxIdx,
+                   b: CtxIdx) -> Result<CtxIdx, ValidationError> {
+        let idx = self.len();
+        if let Some(&operand) = [a, b].iter().find(|&&o| o >= idx) {
+            return Err(ValidationError { idx, operand });
+        }
+        Ok(self.apply_op(a, b, op))
     }
 
     pub fn add(&mut self, idx1: CtxIdx, idx2: CtxIdx) -> CtxIdx {
-        self.apply_op(idx1, idx2, OpType::Add, |a, b| a + b)
+        self.apply_op(idx1, idx2, OpType::Add)
     }
 
     pub fn sub(&mut self, idx1: CtxIdx, idx2: CtxIdx) -> CtxIdx {
-        self.apply_op(idx1, idx2, OpType::Sub, |a, b| a - b)
+        self.apply_op(idx1, idx2, OpType::Sub)
     }
 
+    /// `mul(x, x)` is a valid way to square `x`: the gradients of both
+    /// (aliased) operands accumulate, giving `d/dx = 2x`
     pub fn mul(&mut self, idx1: CtxIdx, idx2: CtxIdx) -> CtxIdx {
-        self.apply_op(idx1, idx2, OpType::Mul, |a, b| a * b)
+        self.apply_op(idx1, idx2, OpType::Mul)
     }
 
     pub fn div(&mut self, idx1: CtxIdx, idx2: CtxIdx) -> CtxIdx {
-        self.apply_op(idx1, idx2, OpType::Div, |a, b| a / b)
+        self.apply_op(idx1, idx2, OpType::Div)
+    }
+
+    /// `a / (b + eps)`, keeping a zero denominator from producing inf/NaN
+    /// values and gradients
+    pub fn safe_div(&mut self, idx1: CtxIdx, idx2: CtxIdx,
+                    eps: f64) -> CtxIdx {
+        let eps = self.push(eps);
+        let denominator = self.add(idx2, eps);
+        self.div(idx1, denominator)
+    }
+
+    /// `safe_div` with the context's `eps`
+    pub fn safe_div_default(&mut self, idx1: CtxIdx, idx2: CtxIdx) -> CtxIdx {
+        self.safe_div(idx1, idx2, self.eps)
     }
 
     pub fn pow(&mut self, base_idx: CtxIdx, exponent_idx: CtxIdx) -> CtxIdx {
-        self.apply_op(base_idx, exponent_idx, OpType::Pow, |a, b| a.powf(b))
+        self.apply_op(base_idx, exponent_idx, OpType::Pow)
     }
 
     pub fn exp(&mut self, idx: CtxIdx) -> CtxIdx {
-        self.apply_op(idx, 0, OpType::Exp, |a, _| a.exp())
+        self.apply_op(idx, idx, OpType::Exp)
     }
 
     pub fn tanh(&mut self, idx: CtxIdx) -> CtxIdx {
-        self.apply_op(idx, 0, OpType::Tanh, |a, _| {
-            let x = core::f64::consts::E.powf(2.0 * a) - 1.0;
-            let y = core::f64::consts::E.powf(2.0 * a) + 1.0;
-            x / y
-        })
+        self.apply_op(idx, idx, OpType::Tanh)
+    }
+
+    /// `tanh` whose derivative is floored at `floor` during backward, so
+    /// saturated nodes keep passing a small gradient
+    pub fn leaky_tanh(&mut self, idx: CtxIdx, floor: f64) -> CtxIdx {
+        self.apply_op(idx, idx, OpType::LeakyTanh(floor))
+    }
+
+    /// Gradient reversal: passes `idx` through unchanged, but multiplies the
+    /// gradient flowing back to it by `-lambda`
+    pub fn grad_reverse(&mut self, idx: CtxIdx, lambda: f64) -> CtxIdx {
+        self.apply_op(idx, idx, OpType::ScaleGrad(-lambda))
+    }
+
+    /// Swish/SiLU `x * sigmoid(x)`. A dedicated op rather than a composition,
+    /// so it costs one node and its sigmoid is evaluated overflow-free
+    pub fn swish(&mut self, idx: CtxIdx) -> CtxIdx {
+        self.apply_op(idx, idx, OpType::Swish)
+    }
+
+    pub fn ln(&mut self, idx: CtxIdx) -> CtxIdx {
+        self.apply_op(idx, idx, OpType::Ln)
+    }
+
+    pub fn sqrt(&mut self, idx: CtxIdx) -> CtxIdx {
+        self.apply_op(idx, idx, OpType::Sqrt)
+    }
+
+    pub fn square(&mut self, idx: CtxIdx) -> CtxIdx {
+        self.apply_op(idx, idx, OpType::Square)
+    }
+
+    pub fn softplus(&mut self, idx: CtxIdx) -> CtxIdx {
+        self.apply_op(idx, idx, OpType::Softplus)
+    }
+
+    /// A pass-through node, for when a graph needs a node per activation
+    /// even where no activation is applied
+    pub fn identity(&mut self, idx: CtxIdx) -> CtxIdx {
+        self.apply_op(idx, idx, OpType::Identity)
     }
 
     pub fn sum(&mut self, indices: &[CtxIdx]) -> CtxIdx {
-        indices.iter().fold(self.push(0.0), |a, &b| self.add(a, b))
+        self.sum_iter(indices.iter().copied())
+    }
+
+    /// Like `sum`, but takes the indices from any iterator, e.g.
+    /// `mlp.parameters()`, without collecting them first
+    pub fn sum_iter<I>(&mut self, indices: I) -> CtxIdx
+    where
+        I: IntoIterator<Item = CtxIdx>,
+    {
+        indices.into_iter().fold(self.push(0.0), |a, b| self.add(a, b))
+    }
+
+    /// Numerically stable softmax. The maximum logit is subtracted first as a
+    /// constant, which doesn't change the result or its gradient
+    pub fn softmax(&mut self, logits: &[CtxIdx]) -> Vec<CtxIdx> {
+        let max = self.values(logits).fold(f64::NEG_INFINITY, f64::max);
+        let max = self.push(max);
+
+        let exps: Vec<CtxIdx> = logits.iter()
+            .map(|&l| {
+                let shifted = self.sub(l, max);
+                self.exp(shifted)
+            })
+            .collect();
+
+        let total = self.sum(&exps);
+        exps.iter().map(|&e| self.div(e, total)).collect()
+    }
+
+    /// `softmax(logits / temperature)`; `temperature` is a regular node, so
+    /// it can be a trainable parameter
+    pub fn softmax_t(&mut self, logits: &[CtxIdx],
+                     temperature: CtxIdx) -> Vec<CtxIdx> {
+        let scaled: Vec<CtxIdx> = logits.iter()
+            .map(|&l| self.div(l, temperature))
+            .collect();
+        self.softmax(&scaled)
+    }
+
+    /// `x_i / sqrt(sum(x_j^2) + eps)` with the context's `eps`
+    pub fn l2_normalize(&mut self, indices: &[CtxIdx]) -> Vec<CtxIdx> {
+        let squares: Vec<CtxIdx> = indices.iter()
+            .map(|&i| self.square(i))
+            .collect();
+        let sum_sq = self.sum(&squares);
+        let eps    = self.push(self.eps);
+        let sum_sq = self.add(sum_sq, eps);
+        let norm   = self.sqrt(sum_sq);
+
+        indices.iter().map(|&i| self.div(i, norm)).collect()
+    }
+
+    /// `sum(a_i * b_i)`; an empty product is `0`
+    pub fn dot(&mut self, a: &[CtxIdx], b: &[CtxIdx]) -> CtxIdx {
+        assert_eq!(a.len(), b.len(), "dot operands differ in length");
+
+        let mut terms = a.iter().zip(b.iter());
+        let Some((&a0, &b0)) = terms.next() else {
+            return self.push(0.0);
+        };
+
+        let first = self.mul(a0, b0);
+        terms.fold(first, |acc, (&ai, &bi)| {
+            let prod = self.mul(ai, bi);
+            self.add(acc, prod)
+        })
+    }
+
+    /// `dot(a, b) / (||a|| * ||b|| + eps)` with the context's `eps`
+    pub fn cosine_similarity(&mut self, a: &[CtxIdx],
+                             b: &[CtxIdx]) -> CtxIdx {
+        let dot = self.dot(a, b);
+
+        let sq_a   = self.dot(a, a);
+        let sq_b   = self.dot(b, b);
+        let norm_a = self.sqrt(sq_a);
+        let norm_b = self.sqrt(sq_b);
+        let norms  = self.mul(norm_a, norm_b);
+
+        self.safe_div_default(dot, norms)
+    }
+
+    /// Element-wise `a + b`, e.g. for residual connections (`layer(x) + x`)
+    pub fn add_vec(&mut self, a: &[CtxIdx], b: &[CtxIdx]) -> Vec<CtxIdx> {
+        assert_eq!(a.len(), b.len(), "add_vec operands differ in length");
+        a.iter().zip(b.iter()).map(|(&x, &y)| self.add(x, y)).collect()
+    }
+
+    /// Flattens several branches' outputs into one input vector, preserving
+    /// order. No graph nodes are created
+    pub fn concat(branches: &[&[CtxIdx]]) -> Vec<CtxIdx> {
+        branches.concat()
+    }
+
+    /// Picks `indices[which]`, e.g. the logit of a chosen action. No node is
+    /// created, so backward through the result reaches only that input.
+    /// Panics if `which` or the picked index is out of bounds
+    pub fn select(&self, indices: &[CtxIdx], which: usize) -> CtxIdx {
+        assert!(which < indices.len(),
+                "selecting {which} out of {} nodes", indices.len());
+
+        let idx = indices[which];
+        assert!(idx < self.len(), "selected node {idx} is not in the context");
+        idx
     }
 
+    /// `sum(indices) / len`; NaN when `indices` is empty
+    pub fn mean(&mut self, indices: &[CtxIdx]) -> CtxIdx {
+        let total = self.sum(indices);
+        let count = self.push(indices.len() as f64);
+        self.div(total, count)
+    }
+
+    /// Computes `d(output)/d(node)` for every node. Gradients accumulate
+    /// over every path, so a parameter shared by several samples' sub-graphs
+    /// gets the sum of its per-sample gradients
     pub fn backward(&mut self, output_idx: CtxIdx) {
-        // Reset all gradients to zero, except for the output node
+        self.backward_from(&[(output_idx, 1.0)]);
+    }
+
+    /// Like `backward` but seeds each listed node with the given gradient
+    /// instead of a single output with `1.0`, which is equivalent to calling
+    /// `backward` on the seed-weighted sum of the nodes
+    pub fn backward_from(&mut self, seeds: &[(CtxIdx, f64)]) {
+        // Reset all gradients to zero, except for the seeded nodes
         self.values.iter_mut().for_each(|v| v.grad = 0.0);
-        self.values[output_idx].grad = 1.0;
+        for &(idx, seed) in seeds {
+            self.values[idx].grad += seed;
+        }
 
-        // Traverse nodes in reverse to accumulate gradients
-        for idx in (0..=output_idx).rev() {
-            let val = self.values[idx].clone();
+        if let Some(last) = seeds.iter().map(|&(idx, _)| idx).max() {
+            // Unchecked propagation never fails
+            let _ = self.propagate(last, false);
+        }
+    }
 
-            // Constants/inputs need no adjustments
-            if val.op.is_none() { continue; }
+    /// Runs `backward` on the mean of `loss_indices` (e.g. per-sample losses
+    /// of a full batch), leaving averaged gradients. Returns the mean node
+    pub fn backward_mean(&mut self, loss_indices: &[CtxIdx]) -> CtxIdx {
+        let mean = self.mean(loss_indices);
+        self.backward(mean);
+        mean
+    }
 
-            let (optype, operands) = val.op.unwrap();
-            match optype {
-                OpType::Add => {
-                    // d(output)/d(a) = 1
-                    // d(output)/d(b) = 1
-                    for &op in &operands {
-                        self.values[op].grad += self.values[idx].grad;
-                    }
-                },
-                OpType::Sub => {
-                    // d(output)/d(a) = 1
-                    // d(output)/d(b) = -1
-                    let idx_a = operands[0];
-                    let idx_b = operands[1];
-                    self.values[idx_a].grad += self.values[idx].grad;
-                    self.values[idx_b].grad -= self.values[idx].grad;
-                }
+    /// Like `backward` but only resets the gradients of op nodes, so leaf
+    /// gradients (inputs/parameters) accumulate across calls, e.g. over the
+    /// samples of a mini-batch. See `scale_param_grads` for averaging
+    pub fn backward_accumulate(&mut self, output_idx: CtxIdx) {
+        self.values.iter_mut()
+            .filter(|v| v.op.is_some())
+            .for_each(|v| v.grad = 0.0);
+        self.values[output_idx].grad += 1.0;
+
+        // Unchecked propagation never fails
+        let _ = self.propagate(output_idx, false);
+    }
+
+    /// Like `backward` but adds to every node's existing gradient instead of
+    /// replacing it, e.g. to inspect the combined gradients of two losses.
+    /// Unlike `backward_accumulate`, which only keeps summing on leaves,
+    /// intermediate (op) nodes end up with the summed gradients too
+    pub fn backward_retain(&mut self, output_idx: CtxIdx) {
+        let previous: Vec<f64> = self.values.iter().map(|v| v.grad).collect();
+        self.backward(output_idx);
+        for (val, grad) in self.values.iter_mut().zip(previous) {
+            val.grad += grad;
+        }
+    }
+
+    /// Multiplies the gradient of every one of `params` by `factor`, e.g.
+    /// `1 / batch_size` after accumulating a batch
+    pub fn scale_param_grads(&mut self, params: &[CtxIdx], factor: f64) {
+        params.iter().for_each(|&p| self.values[p].grad *= factor);
+    }
+
+    /// A functional SGD step: pushes `param - lr * grad` for every one of
+    /// `params` as graph nodes instead of writing in place, so later losses
+    /// can backpropagate through the update (MAML-style). The gradients are
+    /// detached, i.e. treated as constants
+    pub fn sgd_functional(&mut self, params: &[CtxIdx],
+                          lr: f64) -> Vec<CtxIdx> {
+        params.iter()
+            .map(|&p| {
+                let step = self.push(lr * self.grad(p));
+                self.sub(p, step)
+            })
+            .collect()
+    }
+
+    /// Clamps the gradient of every one of `params` into `[-c, c]`; panics
+    /// if `c` is negative or NaN
+    pub fn clip_grad_value(&mut self, params: &[CtxIdx], c: f64) {
+        for &p in params {
+            self.values[p].grad = self.values[p].grad.clamp(-c, c);
+        }
+    }
+
+    /// Builds an expression with `build`, runs `backward` on its result and
+    /// returns the gradients of `inputs`
+    pub fn grad_of(&mut self, build: impl FnOnce(&mut Context) -> CtxIdx,
+                   inputs: &[CtxIdx]) -> Vec<f64> {
+        let output = build(self);
+        self.backward(output);
+        inputs.iter().map(|&i| self.grad(i)).collect()
+    }
+
+    /// Like `backward` but aborts at the first op node whose backward step
+    /// produces a non-finite gradient for one of its operands. Gradients
+    /// are left partially propagated on error
+    pub fn try_backward(&mut self,
+                        output_idx: CtxIdx) -> Result<(), BackwardError> {
+        self.values.iter_mut().for_each(|v| v.grad = 0.0);
+        self.values[output_idx].grad = 1.0;
+        self.propagate(output_idx, true)
+    }
+
+    /// The local derivatives `d(node)/d(operand)` of op node `idx` w.r.t.
+    /// each of its operands, before any chain-rule scaling. `None` for leaves
+    pub fn local_grads(&self, idx: CtxIdx) -> Option<[f64; 2]> {
+        let (optype, operands) = self.values[idx].op?;
+        Some(self.partials(idx, optype, operands))
+    }
+
+    /// Local derivatives of node `idx` (an `optype` over `operands`). Unary
+    /// ops put theirs in the first slot and zero in the second, so that
+    /// summing over both (aliased) operands counts it exactly once
+    fn partials(&self, idx: CtxIdx, optype: OpType,
+                operands: [CtxIdx; 2]) -> [f64; 2] {
+        let out = self.values[idx].data;
+        let a = self.values[operands[0]].data;
+        let b = self.values[operands[1]].data;
+        match optype {
+            // With `add(x, x)` or `mul(x, x)` both operands alias the same
+            // node, so the two partials accumulate to `2` and `2x` as expected
+            OpType::Add      => [1.0, 1.0],
+            OpType::Sub      => [1.0, -1.0],
+            OpType::Mul      => [b, a],
+            OpType::Div      => [1.0 / b, -a / (b * b)],
+            // d/d(a) = b * a^(b-1), d/d(b) = a^b * ln(a)
+            OpType::Pow      => [b * math::powf(a, b - 1.0),
+                                 math::powf(a, b) * math::ln(a)],
+            OpType::Exp      => [math::exp(a), 0.0],
+            OpType::Square   => [2.0 * a, 0.0],
+            OpType::Ln       => [1.0 / a, 0.0],
+            OpType::Identity => [1.0, 0.0],
+            OpType::Softplus => [sigmoid(a), 0.0],
+            // These two are cheaper in terms of the node's own output
+            OpType::Tanh     => [1.0 - math::powi(out, 2), 0.0],
+            OpType::Sqrt     => [1.0 / (2.0 * out), 0.0],
+            // Keeps saturated units (`out == ±1`) trainable
+            OpType::LeakyTanh(floor) => {
+                [(1.0 - math::powi(out, 2)).max(floor), 0.0]
+            },
+            OpType::ScaleGrad(factor) => [factor, 0.0],
+            // s + x * s * (1 - s) with s = sigmoid(x)
+            OpType::Swish    => {
+                let s = sigmoid(a);
+                [s + a * s * (1.0 - s), 0.0]
+            },
+        }
+    }
+
+    /// Pushes the gradients of nodes `0..=from` down to their operands,
+    /// optionally checking that every produced gradient is finite
+    fn propagate(&mut self, from: CtxIdx,
+                 check: bool) -> Result<(), BackwardError> {
+        #[cfg(feature = "std")]
+        let start = self.timing.map(|_| Instant::now());
+
+        let result = self.propagate_untimed(from, check);
+
+        #[cfg(feature = "std")]
+        if let (Some(start), Some((_, backward))) = (start, &mut self.timing) {
+            *backward += start.elapsed();
+        }
+        result
+    }
+
+    fn propagate_untimed(&mut self, from: CtxIdx,
+                         check: bool) -> Result<(), BackwardError> {
+        // Traverse nodes in reverse to accumulate gradients
+        for idx in (0..=from).rev() {
+            // Only the (`Copy`) operation is copied out, not the whole node
+            //
+            // Constants/inputs need no adjustments
+            let Some((optype, operands)) = self.values[idx].op else {
+                continue;
+            };
+
+            // Nodes that don't lead to the output contribute nothing; skipping
+            // them also keeps `0 * inf` from turning their operands' grads NaN
+            if self.values[idx].grad == 0.0 { continue; }
+            let grad = self.values[idx].grad;
+            let partials = self.partials(idx, optype, operands);
+            for (&op, partial) in operands.iter().zip(partials) {
+                // A unary op's zero second slot mustn't yield `0 * inf`
+                if partial != 0.0 {
+                    self.values[op].grad += partial * grad;
+                }
+            }
+
+            let finite = |o: &CtxIdx| self.values[*o].grad.is_finite();
+            if check && !operands.iter().all(finite) {
+                return Err(BackwardError { idx, op: optype });
+            }
+
+            if !self.retain_grad {
+                self.values[idx].grad = 0.0;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_context_is_empty() {
+        assert_eq!(Context::default().len(), 0);
+        assert!(Context::default().is_empty());
+    }
+
+    #[test]
+    fn square_matches_self_mul() {
+        let mut ctx = Context::new();
+        let x = ctx.push(-1.5);
+        let squared    = ctx.square(x);
+        let multiplied = ctx.mul(x, x);
+        assert_eq!(ctx.value(squared), ctx.value(multiplied));
+
+        ctx.backward(squared);
+        assert_eq!(ctx.grad(x), -3.0);
+        ctx.backward(multiplied);
+        assert_eq!(ctx.grad(x), -3.0);
+    }
+
+    #[test]
+    fn recompute_dirty_updates_only_affected_nodes() {
+        let mut ctx = Context::new();
+        let a = ctx.push(1.0);
+        let b = ctx.push(2.0);
+        let ya = ctx.add(a, a);
+        let yb = ctx.mul(b, b);
+
+        // `b` changes without being marked, so a stale `yb` shows that it
+        // wasn't recomputed
+        ctx.get_mut(a).data = 3.0;
+        ctx.get_mut(b).data = 5.0;
+        ctx.mark_dirty(a);
+        ctx.recompute_dirty();
+
+        assert_eq!(ctx.value(ya), 6.0);
+        assert_eq!(ctx.value(yb), 4.0);
+    }
+
+    #[test]
+    fn parameters_excludes_plain_pushes() {
+        let mut ctx = Context::new();
+        let x = ctx.push(1.0);
+        let w = ctx.push_param(2.0);
+        let b = ctx.push_param(3.0);
+        ctx.mul(x, w);
+
+        assert_eq!(ctx.parameters().collect::<Vec<_>>(), [w, b]);
+    }
+
+    #[test]
+    fn with_capacity_avoids_reallocation() {
+        let mut ctx = Context::with_capacity(100);
+        let capacity = ctx.capacity();
+        assert!(capacity >= 100);
+
+        (0..100).for_each(|i| { ctx.push(i as f64); });
+        assert_eq!(ctx.capacity(), capacity);
+
+        ctx.reserve(50);
+        assert!(ctx.capacity() >= 150);
+    }
+
+    /// A pseudo-random DAG of about `n` nodes over the original ops (`add`,
+    /// `sub`, `mul`, `div`, `pow`, `tanh`, `exp`). Every result goes through
+    /// `tanh` so the data stays bounded, and the returned output sums all of
+    /// them, so every node is an ancestor of it
+    fn random_graph(ctx: &mut Context, n: usize) -> CtxIdx {
+        let mut rng = crate::Rng::new(7);
+        let mut pool: Vec<CtxIdx> = (0..16)
+            .map(|_| ctx.push(rng.range(-1.0, 1.0)))
+            .collect();
+
+        // Summing the pool adds one node per entry
+        while ctx.len() + pool.len() < n {
+            let mut pick = || pool[(rng.rand() * pool.len() as f64) as usize];
+            let (a, b) = (pick(), pick());
+            let result = match (rng.rand() * 7.0) as usize {
+                0 => ctx.add(a, b),
+                1 => ctx.sub(a, b),
+                2 => ctx.mul(a, b),
+                3 => {
+                    let denominator = ctx.exp(b);
+                    ctx.div(a, denominator)
+                },
+                4 => {
+                    let base = ctx.exp(a);
+                    ctx.pow(base, b)
+                },
+                5 => ctx.exp(a),
+                _ => a,
+            };
+            pool.push(ctx.tanh(result));
+        }
+
+        ctx.sum(&pool)
+    }
+
+    /// Gradients as computed by the original `backward`, which spelled out
+    /// every op's chain rule step in place
+    fn reference_grads(ctx: &Context, output: CtxIdx) -> Vec<f64> {
+        let mut grads = vec![0.0; ctx.len()];
+        grads[output] = 1.0;
+
+        for idx in (0..=output).rev() {
+            let Some((optype, [ia, ib])) = ctx.get(idx).op else { continue; };
+            let (a, b, grad) = (ctx.value(ia), ctx.value(ib), grads[idx]);
+            match optype {
+                OpType::Add => {
+                    grads[ia] += grad;
+                    grads[ib] += grad;
+                },
+                OpType::Sub => {
+                    grads[ia] += grad;
+                    grads[ib] -= grad;
+                },
                 OpType::Mul => {
-                    // d(output)/d(a) = b
-                    // d(output)/d(b) = a
-                    let idx_a = operands[0];
-                    let idx_b = operands[1];
-                    let a = self.values[idx_a].data;
-                    let b = self.values[idx_b].data;
-                    self.values[idx_a].grad += b * self.values[idx].grad;
-                    self.values[idx_b].grad += a * self.values[idx].grad;
+                    grads[ia] += b * grad;
+                    grads[ib] += a * grad;
                 },
                 OpType::Div => {
-                    // d(output)/d(a) = 1 / b
-                    // d(output)/d(b) = -a / b^2
-                    let idx_a = operands[0];
-                    let idx_b = operands[1];
-                    let a = self.values[idx_a].data;
-                    let b = self.values[idx_b].data;
-                    self.values[idx_a].grad +=
-                        (1.0 / b) * self.values[idx].grad;
-                    self.values[idx_b].grad -=
-                        (a / (b * b)) * self.values[idx].grad;
+                    grads[ia] += (1.0 / b) * grad;
+                    grads[ib] -= (a / (b * b)) * grad;
                 },
                 OpType::Pow => {
-                    // d(output)/d(a) = b * a^(b-1)
-                    // d(output)/d(b) = a^b * ln(a)
-                    let idx_a = operands[0];
-                    let idx_b = operands[1];
-                    let a = self.values[idx_a].data;
-                    let b = self.values[idx_b].data;
-                    self.values[idx_a].grad +=
-                        b * a.powf(b - 1.0) * self.values[idx].grad;
-                    self.values[idx_b].grad +=
-                        a.powf(b) * self.values[idx].grad * a.ln();
+                    grads[ia] += b * a.powf(b - 1.0) * grad;
+                    grads[ib] += a.powf(b) * grad * a.ln();
                 },
                 OpType::Tanh => {
-                    // d(output)/d(x) = 1 - tanh(x)^2
-                    self.values[operands[0]].grad +=
-                        (1.0 - val.data.powi(2)) * self.values[idx].grad;
-                },
-                OpType::Exp => {
-                    // d(output)/d(x) = exp(x)
-                    let idx_a = operands[0];
-                    let a = self.values[idx_a].data;
-                    self.values[idx_a].grad += a.exp() * self.values[idx].grad;
+                    grads[ia] += (1.0 - ctx.value(idx).powi(2)) * grad;
                 },
+                OpType::Exp => grads[ia] += a.exp() * grad,
+                other => unreachable!("{other:?} is not an original op"),
+            }
+        }
+        grads
+    }
+
+    #[test]
+    fn backward_matches_original_implementation() {
+        let mut ctx = Context::new();
+        let output = random_graph(&mut ctx, 5_000);
+        let expected = reference_grads(&ctx, output);
+
+        ctx.backward(output);
+        for (idx, &expected) in expected.iter().enumerate() {
+            let grad = ctx.grad(idx);
+            assert!((grad - expected).abs() <= 1e-12 * expected.abs().max(1.0),
+                    "node {idx}: {grad} != {expected}");
+        }
+    }
+
+    #[test]
+    #[ignore = "benchmark, run with `cargo test --release -- --ignored`"]
+    fn bench_backward_50k_nodes() {
+        let mut ctx = Context::new();
+        let output = random_graph(&mut ctx, 50_000);
+
+        let runs = 100;
+        let start = std::time::Instant::now();
+        (0..runs).for_each(|_| ctx.backward(output));
+        println!("backward over {} nodes: {:?} per run",
+                 ctx.len(), start.elapsed() / runs);
+    }
+
+    #[test]
+    fn grad_map_pairs_indices_with_grads() {
+        let mut ctx = Context::new();
+        let a = ctx.push(2.0);
+        let b = ctx.push(5.0);
+        let y = ctx.mul(a, b);
+        ctx.backward(y);
+
+        assert_eq!(ctx.grad_map(&[b, a]), [(b, 2.0), (a, 5.0)]);
+    }
+
+    #[test]
+    fn add_vec_sums_and_splits_gradients() {
+        let mut ctx = Context::new();
+        let a = ctx.push_many(&[1.0, 2.0]);
+        let b = ctx.push_many(&[10.0, 20.0]);
+        let sums = ctx.add_vec(&a, &b);
+        assert_eq!(ctx.values(&sums).collect::<Vec<_>>(), [11.0, 22.0]);
+
+        // Both branches of a residual connection receive the full gradient
+        let out = ctx.mul(sums[0], sums[1]);
+        ctx.backward(out);
+        assert_eq!(ctx.grad_map(&a), [(a[0], 22.0), (a[1], 11.0)]);
+        assert_eq!(ctx.grad_map(&b), [(b[0], 22.0), (b[1], 11.0)]);
+    }
+
+    #[test]
+    fn concat_preserves_order() {
+        let mut ctx = Context::new();
+        let (a, b, c) = (ctx.push(1.0), ctx.push(2.0), ctx.push(3.0));
+
+        assert_eq!(Context::concat(&[&[a, b], &[c]]), [a, b, c]);
+        assert_eq!(ctx.len(), 3);
+    }
+
+    #[test]
+    fn softplus_is_stable() {
+        let mut ctx = Context::new();
+        let zero = ctx.push(0.0);
+        let grad = ctx.grad_of(|ctx| ctx.softplus(zero), &[zero]);
+        assert_eq!(ctx.value(ctx.len() - 1), core::f64::consts::LN_2);
+        assert_eq!(grad, [0.5]);
+
+        let large = ctx.push(1000.0);
+        let grad = ctx.grad_of(|ctx| ctx.softplus(large), &[large]);
+        assert_eq!(ctx.value(ctx.len() - 1), 1000.0);
+        assert_eq!(grad, [1.0]);
+    }
+
+    #[test]
+    fn compact_keeps_ancestors_and_remaps_operands() {
+        let mut ctx = Context::new();
+        let a = ctx.push(2.0);
+        let dropped = ctx.push(100.0);
+        let b = ctx.push(3.0);
+        ctx.mul(dropped, a);
+        let sum = ctx.add(a, b);
+        let out = ctx.mul(sum, b);
+        ctx.backward(out);
+
+        let kept = ctx.compact(&[out, a]);
+        assert_eq!(ctx.len(), 4);
+        assert_eq!(kept, [3, 0]);
+        assert_eq!(ctx.value(kept[0]), 15.0);
+        assert_eq!(ctx.value(kept[1]), 2.0);
+        assert!(ctx.values(&[0, 1, 2, 3]).all(|v| v != 100.0));
+        assert_eq!(ctx.get(2).op, Some((OpType::Add, [0, 1])));
+        assert_eq!(ctx.get(3).op, Some((OpType::Mul, [2, 1])));
+        assert!((0..4).all(|i| ctx.grad(i) == 0.0));
+        assert!(ctx.validate().is_ok());
+
+        // The remapped graph still differentiates correctly
+        ctx.backward(kept[0]);
+        assert_eq!(ctx.grad(kept[1]), 3.0);
+    }
+
+    #[test]
+    fn index_matches_accessors() {
+        let mut ctx = Context::new();
+        let a = ctx.push(4.0);
+        let b = ctx.exp(a);
+        assert_eq!(ctx[b].data, ctx.value(b));
+
+        ctx[a].data = 1.0;
+        assert_eq!(ctx.value(a), 1.0);
+    }
+
+    #[test]
+    fn dot_grads_are_the_other_operand() {
+        let mut ctx = Context::new();
+        let a = ctx.push_many(&[1.0, -2.0, 3.0]);
+        let b = ctx.push_many(&[4.0, 5.0, -6.0]);
+
+        let out = ctx.dot(&a, &b);
+        assert_eq!(ctx.value(out), -24.0);
+
+        ctx.backward(out);
+        assert_eq!(ctx.values(&b).collect::<Vec<_>>(),
+                   a.iter().map(|&i| ctx.grad(i)).collect::<Vec<_>>());
+        assert_eq!(ctx.values(&a).collect::<Vec<_>>(),
+                   b.iter().map(|&i| ctx.grad(i)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn bind_matches_a_fresh_build() {
+        fn build(ctx: &mut Context, x: &[CtxIdx]) -> CtxIdx {
+            let prod = ctx.mul(x[0], x[1]);
+            let sum  = ctx.add(prod, x[2]);
+            ctx.tanh(sum)
+        }
+
+        let mut ctx = Context::new();
+        let slots = ctx.placeholders(3);
+        let out = build(&mut ctx, &slots);
+
+        for inputs in [[0.5, -1.0, 2.0], [3.0, 0.25, -0.5]] {
+            ctx.bind(&slots, &inputs);
+            ctx.recompute_dirty();
+
+            let mut fresh = Context::new();
+            let x = fresh.push_many(&inputs);
+            let expected = build(&mut fresh, &x);
+            assert_eq!(ctx.value(out), fresh.value(expected));
+        }
+    }
+
+    #[test]
+    fn backward_from_matches_weighted_sum() {
+        let mut ctx = Context::new();
+        let x = ctx.push(0.5);
+        let y = ctx.push(-2.0);
+        let p = ctx.mul(x, y);
+        let q = ctx.tanh(x);
+
+        ctx.backward_from(&[(p, 2.0), (q, -3.0)]);
+        let seeded = ctx.grad_map(&[x, y]);
+
+        let two   = ctx.push(2.0);
+        let three = ctx.push(-3.0);
+        let wp    = ctx.mul(p, two);
+        let wq    = ctx.mul(q, three);
+        let total = ctx.add(wp, wq);
+        ctx.backward(total);
+        assert_eq!(seeded, ctx.grad_map(&[x, y]));
+    }
+
+    #[test]
+    fn identity_passes_value_and_gradient_through() {
+        let mut ctx = Context::new();
+        let x = ctx.push(-0.75);
+        let id = ctx.identity(x);
+        assert_eq!(ctx.value(id), -0.75);
+
+        let three = ctx.push(3.0);
+        let out = ctx.mul(id, three);
+        ctx.backward(out);
+        assert_eq!(ctx.grad(id), 3.0);
+        assert_eq!(ctx.grad(x), 3.0);
+    }
+
+    #[test]
+    fn iter_grads_topo_puts_operands_first() {
+        let mut ctx = Context::new();
+        let a = ctx.push(1.0);
+        let unrelated = ctx.push(9.0);
+        let b = ctx.push(2.0);
+        let prod = ctx.mul(a, b);
+        let out = ctx.add(prod, a);
+        ctx.backward(out);
+
+        let order: Vec<(CtxIdx, f64)> = ctx.iter_grads_topo(out).collect();
+        assert_eq!(order, [(a, 3.0), (b, 1.0), (prod, 1.0), (out, 1.0)]);
+        assert!(order.iter().all(|&(idx, _)| idx != unrelated));
+
+        let position = |idx| order.iter().position(|&(i, _)| i == idx);
+        for &(idx, _) in order.iter() {
+            if let Some((_, operands)) = ctx.get(idx).op {
+                assert!(operands.iter().all(|&o| position(o) < position(idx)));
             }
         }
     }
+
+    #[test]
+    fn softmax_temperature_flattens() {
+        let mut ctx = Context::new();
+        let logits = ctx.push_many(&[2.0, 1.0, -1.0]);
+        let one = ctx.push(1.0);
+        let hot = ctx.push(4.0);
+
+        let plain: Vec<f64> = {
+            let probs = ctx.softmax(&logits);
+            ctx.values(&probs).collect()
+        };
+        let unit: Vec<f64> = {
+            let probs = ctx.softmax_t(&logits, one);
+            ctx.values(&probs).collect()
+        };
+        let flat: Vec<f64> = {
+            let probs = ctx.softmax_t(&logits, hot);
+            ctx.values(&probs).collect()
+        };
+
+        assert_eq!(plain, unit);
+        assert!(flat[0] < plain[0] && flat[2] > plain[2]);
+        assert!((flat.iter().sum::<f64>() - 1.0).abs() < 1e-12);
+
+        // The temperature is differentiable like any other node
+        let probs = ctx.softmax_t(&logits, hot);
+        ctx.backward(probs[0]);
+        assert!(ctx.grad(hot) < 0.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "non-finite node 2 (op: Some((Div, [0, 1])))")]
+    fn assert_finite_reports_division_by_zero() {
+        let mut ctx = Context::new();
+        let a = ctx.push(1.0);
+        let zero = ctx.push(0.0);
+        ctx.div(a, zero);
+        ctx.assert_finite();
+    }
+
+    #[test]
+    fn safe_div_by_zero_stays_finite() {
+        let mut ctx = Context::new();
+        let a = ctx.push(1.0);
+        let zero = ctx.push(0.0);
+
+        let grads = ctx.grad_of(|ctx| ctx.safe_div(a, zero, 1e-8),
+                                &[a, zero]);
+        let out = ctx.value(ctx.len() - 1);
+        assert!(out.is_finite() && (out - 1e8).abs() < 1e-4);
+        assert!(grads.iter().all(|g| g.is_finite()));
+        ctx.assert_finite();
+    }
+
+    #[test]
+    fn push_many_is_contiguous() {
+        let mut ctx = Context::new();
+        ctx.push(0.0);
+        let indices = ctx.push_many(&[1.5, -2.0, 3.25]);
+
+        assert_eq!(indices, [1, 2, 3]);
+        assert_eq!(ctx.values(&indices).collect::<Vec<_>>(),
+                   [1.5, -2.0, 3.25]);
+    }
+
+    #[test]
+    fn eps_controls_safe_div() {
+        let mut ctx = Context::new();
+        assert_eq!(ctx.eps(), 1e-12);
+        let a = ctx.push(1.0);
+        let b = ctx.push(1.0);
+
+        let tight = ctx.safe_div_default(a, b);
+        ctx.set_eps(1.0);
+        let loose = ctx.safe_div_default(a, b);
+        assert!((ctx.value(tight) - 1.0).abs() < 1e-11);
+        assert_eq!(ctx.value(loose), 0.5);
+    }
+
+    #[test]
+    fn is_leaf_and_op_of() {
+        let mut ctx = Context::new();
+        let c = ctx.push(1.0);
+        let t = ctx.tanh(c);
+
+        assert!(ctx.is_leaf(c));
+        assert_eq!(ctx.op_of(c), None);
+        assert!(!ctx.is_leaf(t));
+        assert_eq!(ctx.op_of(t), Some(&OpType::Tanh));
+    }
+
+    #[test]
+    fn l2_normalize_gives_unit_norm_and_couples_grads() {
+        let mut ctx = Context::new();
+        let x = ctx.push_many(&[3.0, -4.0, 12.0]);
+        let normed = ctx.l2_normalize(&x);
+        assert!((ctx.value_norm(&normed) - 1.0).abs() < 1e-12);
+
+        // Every output depends on every input through the shared norm
+        for &out in normed.iter() {
+            ctx.backward(out);
+            assert!(x.iter().all(|&i| ctx.grad(i) != 0.0));
+        }
+    }
+
+    #[test]
+    fn grad_stats_summarizes_grads() {
+        let mut ctx = Context::new();
+        let params = ctx.push_many(&[0.0; 4]);
+        for (&p, grad) in params.iter().zip([-2.0, 0.5, 4.0, 1.5]) {
+            ctx.get_mut(p).grad = grad;
+        }
+
+        assert_eq!(ctx.grad_stats(&params), (-2.0, 4.0, 1.0));
+        let (min, max, mean) = ctx.grad_stats(&[]);
+        assert!(min.is_nan() && max.is_nan() && mean.is_nan());
+    }
+
+    #[test]
+    fn try_backward_reports_ln_of_zero() {
+        let mut ctx = Context::new();
+        let x = ctx.push(0.0);
+        let two = ctx.push(2.0);
+        let ln = ctx.ln(x);
+        let out = ctx.add(ln, two);
+
+        assert_eq!(ctx.try_backward(out),
+                   Err(BackwardError { idx: ln, op: OpType::Ln }));
+
+        let y = ctx.push(1.0);
+        let fine = ctx.ln(y);
+        assert_eq!(ctx.try_backward(fine), Ok(()));
+        assert_eq!(ctx.grad(y), 1.0);
+    }
+
+    /// Compares the gradients `backward` gives `build`'s output w.r.t. its
+    /// inputs against central finite differences
+    fn check_grads(build: impl Fn(&mut Context, &[CtxIdx]) -> CtxIdx,
+                   inputs: &[f64]) {
+        let eval = |inputs: &[f64]| {
+            let mut ctx = Context::new();
+            let x = ctx.push_many(inputs);
+            let out = build(&mut ctx, &x);
+            ctx.value(out)
+        };
+
+        let mut ctx = Context::new();
+        let x = ctx.push_many(inputs);
+        let out = build(&mut ctx, &x);
+        ctx.backward(out);
+
+        let h = 1e-6;
+        for (i, &xi) in x.iter().enumerate() {
+            let mut up = inputs.to_vec();
+            let mut down = inputs.to_vec();
+            up[i] += h;
+            down[i] -= h;
+
+            let numeric = (eval(&up) - eval(&down)) / (2.0 * h);
+            assert!((ctx.grad(xi) - numeric).abs() < 1e-6,
+                    "input {i}: {} != {numeric}", ctx.grad(xi));
+        }
+    }
+
+    #[test]
+    fn cosine_similarity_of_aligned_vectors() {
+        let mut ctx = Context::new();
+        let a = ctx.push_many(&[1.0, -2.0, 3.0]);
+        let neg = ctx.push_many(&[-1.0, 2.0, -3.0]);
+
+        let same = ctx.cosine_similarity(&a, &a);
+        let opposite = ctx.cosine_similarity(&a, &neg);
+        assert!((ctx.value(same) - 1.0).abs() < 1e-9);
+        assert!((ctx.value(opposite) + 1.0).abs() < 1e-9);
+
+        check_grads(|ctx, x| ctx.cosine_similarity(&x[..3], &x[3..]),
+                    &[1.0, -2.0, 3.0, 0.5, 4.0, -1.0]);
+    }
+
+    #[test]
+    fn clone_is_independent() {
+        let mut ctx = Context::new();
+        let a = ctx.push(1.0);
+        let b = ctx.exp(a);
+
+        let mut copy = ctx.clone();
+        copy[a].data = 5.0;
+        copy.mark_dirty(a);
+        copy.recompute_dirty();
+        copy.push(0.0);
+
+        assert_eq!(ctx.len(), 2);
+        assert_eq!(ctx.value(a), 1.0);
+        assert_eq!(ctx.value(b), math::exp(1.0));
+        assert_eq!(copy.value(b), math::exp(5.0));
+    }
+
+    #[test]
+    fn grad_of_mul_is_the_other_operand() {
+        let mut ctx = Context::new();
+        let a = ctx.push(3.0);
+        let b = ctx.push(-4.0);
+        assert_eq!(ctx.grad_of(|ctx| ctx.mul(a, b), &[a, b]), [-4.0, 3.0]);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn timings_never_decrease() {
+        let mut ctx = Context::new();
+        assert_eq!(ctx.timings(), (Duration::ZERO, Duration::ZERO));
+        ctx.enable_timing(true);
+
+        let mut previous = ctx.timings();
+        let mut x = ctx.push(0.5);
+        for _ in 0..3 {
+            (0..1000).for_each(|_| x = ctx.tanh(x));
+            ctx.backward(x);
+
+            let (forward, backward) = ctx.timings();
+            assert!(forward >= previous.0 && backward >= previous.1);
+            previous = (forward, backward);
+        }
+        assert!(previous.0 > Duration::ZERO && previous.1 > Duration::ZERO);
+
+        ctx.enable_timing(false);
+        assert_eq!(ctx.timings(), (Duration::ZERO, Duration::ZERO));
+    }
+
+    #[test]
+    fn dropping_intermediate_grads_keeps_leaf_grads() {
+        let build = |ctx: &mut Context| {
+            let w = ctx.push_param(0.5);
+            let x = ctx.push(2.0);
+            let hidden = ctx.mul(w, x);
+            let out = ctx.tanh(hidden);
+            (w, x, hidden, out)
+        };
+
+        let mut retained = Context::new();
+        let (w, x, hidden, out) = build(&mut retained);
+        retained.backward(out);
+        assert_ne!(retained.grad(hidden), 0.0);
+
+        let mut dropped = Context::new();
+        dropped.set_retain_grad(false);
+        build(&mut dropped);
+        dropped.backward(out);
+        assert_eq!(dropped.grad(hidden), 0.0);
+        assert_eq!(dropped.grad(out), 0.0);
+        assert_eq!(dropped.grad_map(&[w, x]), retained.grad_map(&[w, x]));
+    }
+
+    #[test]
+    fn scaled_accumulation_averages_grads() {
+        let mut ctx = Context::new();
+        let w = ctx.push_param(3.0);
+        let x1 = ctx.push(2.0);
+        let x2 = ctx.push(-6.0);
+
+        let first = ctx.mul(w, x1);
+        ctx.backward_accumulate(first);
+        let second = ctx.mul(w, x2);
+        ctx.backward_accumulate(second);
+        assert_eq!(ctx.grad(w), -4.0);
+
+        ctx.scale_param_grads(&[w], 0.5);
+        assert_eq!(ctx.grad(w), -2.0);
+    }
+
+    #[test]
+    fn graph_depth_of_linear_and_balanced_sums() {
+        let mut ctx = Context::new();
+        let leaves = ctx.push_many(&[1.0; 16]);
+        assert_eq!(ctx.graph_depth(leaves[0]), 0);
+
+        let linear = ctx.sum(&leaves);
+        assert_eq!(ctx.graph_depth(linear), 16);
+
+        let mut level = leaves;
+        while level.len() > 1 {
+            level = level.chunks(2).map(|p| ctx.add(p[0], p[1])).collect();
+        }
+        assert_eq!(ctx.value(level[0]), ctx.value(linear));
+        assert_eq!(ctx.graph_depth(level[0]), 4);
+    }
+
+    #[test]
+    fn local_grads_are_the_op_derivatives() {
+        let mut ctx = Context::new();
+        let a = ctx.push(3.0);
+        let b = ctx.push(-2.0);
+        let prod = ctx.mul(a, b);
+        let quot = ctx.div(a, b);
+        let tanh = ctx.tanh(b);
+
+        assert_eq!(ctx.local_grads(a), None);
+        assert_eq!(ctx.local_grads(prod), Some([-2.0, 3.0]));
+        assert_eq!(ctx.local_grads(quot), Some([-0.5, -0.75]));
+        assert_eq!(ctx.local_grads(tanh),
+                   Some([1.0 - ctx.value(tanh).powi(2), 0.0]));
+
+        // Local derivatives don't depend on the gradient flowing in
+        ctx.backward(prod);
+        assert_eq!(ctx.local_grads(prod), Some([-2.0, 3.0]));
+    }
+
+    #[test]
+    fn shared_param_grads_sum_over_samples() {
+        let mut ctx = Context::new();
+        let w = ctx.push_param(0.5);
+        let samples = [(2.0, 3.0), (-1.0, 4.0)];
+
+        let losses: Vec<_> = samples.iter().map(|&(x, y)| {
+            let x = ctx.push(x);
+            let y = ctx.push(y);
+            let pred = ctx.mul(w, x);
+            let diff = ctx.sub(pred, y);
+            ctx.square(diff)
+        }).collect();
+        let total = ctx.add(losses[0], losses[1]);
+        ctx.backward(total);
+
+        // d/dw (w x - y)^2 = 2 (w x - y) x, summed over the samples
+        let expected: f64 = samples.iter()
+            .map(|&(x, y)| 2.0 * (0.5 * x - y) * x)
+            .sum();
+        assert_eq!(ctx.grad(w), expected);
+    }
+
+    #[test]
+    fn push_iter_takes_any_iterator() {
+        let mut ctx = Context::new();
+        ctx.push(-1.0);
+        let idxs = ctx.push_iter((0..5).map(|i| i as f64));
+
+        assert_eq!(idxs, [1, 2, 3, 4, 5]);
+        assert_eq!(ctx.values(&idxs).collect::<Vec<_>>(),
+                   [0.0, 1.0, 2.0, 3.0, 4.0]);
+    }
+
+    #[test]
+    fn self_mul_grad_is_twice_the_operand() {
+        for x in [-3.0, 0.5, 2.0] {
+            let mut ctx = Context::new();
+            let x_idx = ctx.push(x);
+            let y = ctx.mul(x_idx, x_idx);
+            ctx.backward(y);
+            assert_eq!(ctx.grad(x_idx), 2.0 * x);
+        }
+    }
+
+    #[test]
+    fn set_op_rewrites_a_node_and_its_consumers() {
+        let mut ctx = Context::new();
+        let x = ctx.push(0.5);
+        let two = ctx.push(2.0);
+        let act = ctx.tanh(x);
+        let out = ctx.mul(act, two);
+
+        let before = ctx.value(out);
+
+        ctx.set_op(act, OpType::Exp, [x, x]);
+        assert_eq!(ctx.value(act), math::exp(0.5));
+        assert_eq!(ctx.value(out), before);
+
+        ctx.recompute_dirty();
+        assert_eq!(ctx.value(out), 2.0 * math::exp(0.5));
+
+        ctx.backward(out);
+        assert_eq!(ctx.grad(x), 2.0 * math::exp(0.5));
+    }
+
+    #[test]
+    fn leaky_tanh_keeps_saturated_nodes_trainable() {
+        let mut ctx = Context::new();
+        let x = ctx.push(30.0);
+        let tanh = ctx.tanh(x);
+        let leaky = ctx.leaky_tanh(x, 0.01);
+        assert_eq!(ctx.value(tanh), 1.0);
+        assert_eq!(ctx.value(leaky), 1.0);
+
+        ctx.backward(tanh);
+        assert_eq!(ctx.grad(x), 0.0);
+        ctx.backward(leaky);
+        assert_eq!(ctx.grad(x), 0.01);
+
+        // Away from saturation it's the plain tanh derivative
+        let y = ctx.push(0.2);
+        let leaky = ctx.leaky_tanh(y, 0.01);
+        ctx.backward(leaky);
+        assert_eq!(ctx.grad(y), 1.0 - math::powi(ctx.value(leaky), 2));
+    }
+
+    #[test]
+    fn eval_and_truncate_reads_then_shrinks() {
+        let mut ctx = Context::new();
+        let w = ctx.push_param(3.0);
+        let mark = ctx.len();
+
+        let x = ctx.push(2.0);
+        let prod = ctx.mul(w, x);
+        let sum = ctx.add(prod, w);
+        assert_eq!(ctx.eval_and_truncate(mark, &[w, prod, sum]),
+                   [3.0, 6.0, 9.0]);
+        assert_eq!(ctx.len(), mark);
+        assert_eq!(ctx.value(w), 3.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "context exceeded its limit of 3 nodes")]
+    fn pushing_past_max_nodes_panics() {
+        let mut ctx = Context::new();
+        ctx.set_max_nodes(3);
+        let a = ctx.push(1.0);
+        let b = ctx.push(2.0);
+        ctx.add(a, b);
+        ctx.push(3.0);
+    }
+
+    #[test]
+    fn clip_grad_value_clamps_only_large_grads() {
+        let mut ctx = Context::new();
+        let params: Vec<_> = (0..3).map(|_| ctx.push_param(0.0)).collect();
+        for (&p, grad) in params.iter().zip([10.0, 0.3, -4.0]) {
+            ctx[p].grad = grad;
+        }
+
+        ctx.clip_grad_value(&params, 1.0);
+        assert_eq!(params.iter().map(|&p| ctx.grad(p)).collect::<Vec<_>>(),
+                   [1.0, 0.3, -1.0]);
+    }
+
+    #[test]
+    fn backward_retain_sums_the_gradients_of_both_outputs() {
+        let mut ctx = Context::new();
+        let x = ctx.push(3.0);
+        let sq = ctx.square(x);
+        let tripled = ctx.push(3.0);
+        let tripled = ctx.mul(x, tripled);
+
+        ctx.backward_retain(sq);
+        assert_eq!(ctx.grad(x), 6.0);
+        ctx.backward_retain(tripled);
+        assert_eq!(ctx.grad(x), 6.0 + 3.0);
+
+        // A plain backward starts over
+        ctx.backward(tripled);
+        assert_eq!(ctx.grad(x), 3.0);
+    }
+
+    #[test]
+    fn sum_iter_matches_sum() {
+        let mut ctx = Context::new();
+        let params: Vec<_> = [0.5, -2.0, 4.0].iter()
+            .map(|&v| ctx.push_param(v))
+            .collect();
+
+        let from_slice = ctx.sum(&params);
+        let from_iter = ctx.sum_iter(params.iter().copied());
+        assert_eq!(ctx.value(from_iter), ctx.value(from_slice));
+        assert_eq!(ctx.value(from_iter), 2.5);
+
+        ctx.backward(from_iter);
+        assert!(params.iter().all(|&p| ctx.grad(p) == 1.0));
+    }
+
+    #[test]
+    fn restore_undoes_an_optimizer_step() {
+        use crate::{Optimizer, Sgd};
+
+        let mut ctx = Context::new();
+        let params: Vec<_> = [0.1, -0.7, 2.3].iter()
+            .map(|&v| ctx.push_param(v))
+            .collect();
+        let snapshot = ctx.snapshot_values(&params);
+
+        let loss = ctx.dot(&params, &params);
+        ctx.backward(loss);
+        Sgd::new(0.5).step(&mut ctx, &params);
+        assert_ne!(ctx.snapshot_values(&params), snapshot);
+
+        ctx.restore_values(&params, &snapshot);
+        assert_eq!(ctx.snapshot_values(&params), [0.1, -0.7, 2.3]);
+    }
+
+    #[test]
+    fn select_only_passes_gradient_to_the_chosen_input() {
+        let mut ctx = Context::new();
+        let logits = ctx.push_many(&[0.2, 1.5, -0.3]);
+        let chosen = ctx.select(&logits, 1);
+        assert_eq!(chosen, logits[1]);
+
+        let loss = ctx.square(chosen);
+        ctx.backward(loss);
+        assert_eq!(ctx.grad_map(&logits),
+                   [(logits[0], 0.0), (logits[1], 3.0), (logits[2], 0.0)]);
+    }
+
+    #[test]
+    #[should_panic(expected = "selecting 3 out of 3 nodes")]
+    fn select_rejects_out_of_range_positions() {
+        let mut ctx = Context::new();
+        let logits = ctx.push_many(&[0.2, 1.5, -0.3]);
+        ctx.select(&logits, 3);
+    }
+
+    #[test]
+    fn grad_reverse_negates_and_scales_the_gradient() {
+        let mut ctx = Context::new();
+        let x = ctx.push(1.5);
+        let reversed = ctx.grad_reverse(x, 0.3);
+        assert_eq!(ctx.value(reversed), 1.5);
+
+        let loss = ctx.square(reversed);
+        ctx.backward(loss);
+        assert_eq!(ctx.grad(reversed), 3.0);
+        assert_eq!(ctx.grad(x), -0.3 * 3.0);
+    }
+
+    #[test]
+    fn sgd_functional_steps_and_stays_differentiable() {
+        let mut ctx = Context::new();
+        let w = ctx.push_param(2.0);
+        let inner = ctx.square(w);
+        ctx.backward(inner);
+
+        // w' = w - lr * 2w, with the gradient detached
+        let updated = ctx.sgd_functional(&[w], 0.1);
+        assert_eq!(ctx.value(updated[0]), 2.0 - 0.1 * 4.0);
+        assert_eq!(ctx.value(w), 2.0);
+
+        let outer = ctx.square(updated[0]);
+        ctx.backward(outer);
+        assert_eq!(ctx.grad(w), 2.0 * ctx.value(updated[0]));
+    }
+
+    #[test]
+    fn fused_reciprocals_keep_values_and_grads() {
+        let mut ctx = Context::new();
+        let x = ctx.push(3.0);
+        let y = ctx.push(-2.0);
+        let d = ctx.push(0.7);
+        let q1 = ctx.div(x, d);
+        let q2 = ctx.div(y, d);
+        let q3 = ctx.div(x, y);
+        let sum = ctx.add(q1, q2);
+        let out = ctx.add(sum, q3);
+
+        let divs = |ctx: &Context| (0..ctx.len())
+            .filter(|&i| ctx.op_of(i) == Some(&OpType::Div))
+            .count();
+        let mut plain = ctx.clone();
+        plain.backward(out);
+
+        let remap = ctx.fuse_common_reciprocals();
+        assert_eq!(remap.len(), plain.len());
+        assert_eq!(ctx.len(), plain.len() + 2);
+        assert_eq!(ctx.validate(), Ok(()));
+        // `q1` and `q2` share one reciprocal, `q3` keeps its `div`
+        assert_eq!((divs(&plain), divs(&ctx)), (3, 2));
+
+        ctx.backward(remap[out]);
+        for (old, &new) in remap.iter().enumerate() {
+            assert!((ctx.value(new) - plain.value(old)).abs() < 1e-12);
+            assert!((ctx.grad(new) - plain.grad(old)).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn validate_reports_forward_references() {
+        let mut ctx = Context::new();
+        let a = ctx.push(1.0);
+        let b = ctx.push(2.0);
+        let sum = ctx.add(a, b);
+        let prod = ctx.mul(sum, b);
+        assert_eq!(ctx.validate(), Ok(()));
+
+        // Point `sum` at itself and `prod` past the end; the first is reported
+        ctx.get_mut(sum).op = Some((OpType::Add, [a, sum]));
+        ctx.get_mut(prod).op = Some((OpType::Mul, [sum, 9]));
+        assert_eq!(ctx.validate(),
+                   Err(ValidationError { idx: sum, operand: sum }));
+
+        ctx.get_mut(sum).op = Some((OpType::Add, [a, b]));
+        assert_eq!(ctx.validate(),
+                   Err(ValidationError { idx: prod, operand: 9 }));
+    }
+
+    #[test]
+    fn backward_mean_averages_per_sample_grads() {
+        let mut ctx = Context::new();
+        let w = ctx.push_param(1.5);
+        let losses: Vec<_> = [2.0, -1.0, 0.5].iter()
+            .map(|&x| {
+                let x = ctx.push(x);
+                let pred = ctx.mul(w, x);
+                ctx.square(pred)
+            })
+            .collect();
+
+        // d/dw (w x)^2 = 2 w x^2 per sample
+        let per_sample: Vec<f64> = losses.iter()
+            .map(|&loss| {
+                ctx.backward(loss);
+                ctx.grad(w)
+            })
+            .collect();
+        assert_eq!(per_sample, [12.0, 3.0, 0.75]);
+
+        let mean = ctx.backward_mean(&losses);
+        let total: f64 = ctx.values(&losses).sum();
+        assert_eq!(ctx.value(mean), total / 3.0);
+        assert!((ctx.grad(w) - per_sample.iter().sum::<f64>() / 3.0).abs()
+                < 1e-12);
+    }
+
+    #[test]
+    fn swish_is_zero_at_zero_and_identity_for_large_inputs() {
+        let mut ctx = Context::new();
+        let zero = ctx.push(0.0);
+        let out = ctx.swish(zero);
+        assert_eq!(ctx.value(out), 0.0);
+
+        for x in [20.0, 50.0, 800.0] {
+            let idx = ctx.push(x);
+            let out = ctx.swish(idx);
+            assert!((ctx.value(out) - x).abs() < 1e-6 * x);
+        }
+
+        // Overflow-free far into the negative side as well
+        let idx = ctx.push(-800.0);
+        let out = ctx.swish(idx);
+        assert_eq!(ctx.value(out), 0.0);
+
+        for x in [-3.0, -0.5, 0.0, 0.7, 4.0] {
+            check_grads(|ctx, x| ctx.swish(x[0]), &[x]);
+        }
+    }
+
+    #[test]
+    fn op_node_rejects_operands_out_of_range() {
+        let mut ctx = Context::new();
+        let a = ctx.push(2.0);
+        let b = ctx.push(5.0);
+
+        let pow = ctx.op_node(OpType::Pow, a, b).unwrap();
+        assert_eq!(ctx.value(pow), 32.0);
+        assert_eq!(ctx.op_node(OpType::Mul, a, 3),
+                   Err(ValidationError { idx: 3, operand: 3 }));
+        assert_eq!(ctx.op_node(OpType::Exp, 7, 7),
+                   Err(ValidationError { idx: 3, operand: 7 }));
+        assert_eq!(ctx.len(), 3);
+    }
+
+    #[test]
+    fn eval_op_matches_the_graph_forward() {
+        let mut ctx = Context::new();
+        for x in [-3.0, -0.5, 0.0, 0.25, 2.0] {
+            let idx = ctx.push(x);
+            let tanh = ctx.tanh(idx);
+            assert_eq!(eval_op(&OpType::Tanh, x, x), ctx.value(tanh));
+            assert!((eval_op(&OpType::Tanh, x, x) - x.tanh()).abs() < 1e-15);
+        }
+
+        // Binary ops take their operands in order
+        assert_eq!(eval_op(&OpType::Sub, 5.0, 2.0), 3.0);
+        assert_eq!(eval_op(&OpType::Div, 1.0, 4.0), 0.25);
+    }
+
+    #[test]
+    fn expr_renders_infix_and_functions() {
+        let mut ctx = Context::new();
+        let a = ctx.push(1.0);
+        let b = ctx.push(2.0);
+        let c = ctx.push(3.0);
+        let sum = ctx.add(a, b);
+        let prod = ctx.mul(sum, c);
+        assert_eq!(ctx.expr(prod), "((v0 + v1) * v2)");
+
+        let leaky = ctx.leaky_tanh(prod, 0.5);
+        assert_eq!(ctx.expr(leaky), "leaky_tanh(((v0 + v1) * v2), 0.5)");
+        assert_eq!(ctx.expr(a), "v0");
+    }
+
+    #[test]
+    fn expr_writes_shared_nodes_once() {
+        let mut ctx = Context::new();
+        let a = ctx.push(1.0);
+        let sq = ctx.mul(a, a);
+        let t = ctx.tanh(sq);
+        let out = ctx.add(sq, t);
+        assert_eq!(ctx.expr(out), "(#1=(v0 * v0) + tanh(#1))");
+
+        // Every doubling re-uses the previous node twice; written out in full
+        // this would be 2^22 leaves long
+        let mut x = a;
+        for _ in 0..22 {
+            x = ctx.add(x, x);
+        }
+        assert!(ctx.expr(x).len() < 22 * 16);
+    }
+
+    #[test]
+    fn clear_grads_only_touches_the_listed_nodes() {
+        let mut ctx = Context::new();
+        let nodes = ctx.push_many(&[1.0, 2.0, 3.0, 4.0]);
+        let total = ctx.dot(&nodes, &nodes);
+        ctx.backward(total);
+
+        ctx.clear_grads(&nodes[..2]);
+        ctx.clear_grads_iter([nodes[3]]);
+        assert_eq!(ctx.grad_map(&nodes), [(nodes[0], 0.0), (nodes[1], 0.0),
+                                          (nodes[2], 6.0), (nodes[3], 0.0)]);
+        assert_eq!(ctx.grad(total), 1.0);
+    }
+
+    #[test]
+    fn truncate_keeps_pending_recomputes_of_survivors() {
+        let mut ctx = Context::new();
+        let x = ctx.push(1.0);
+        let y = ctx.square(x);
+        let mark = ctx.len();
+
+        ctx.bind(&[x], &[3.0]);
+        let scratch = ctx.push(2.0);
+        let scratch = ctx.mul(y, scratch);
+        assert_eq!(ctx.eval_and_truncate(mark, &[scratch]), [2.0]);
+
+        ctx.recompute_dirty();
+        assert_eq!(ctx.value(y), 9.0);
+    }
 }